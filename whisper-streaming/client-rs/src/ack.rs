@@ -0,0 +1,65 @@
+//! Audio the server hasn't acknowledged yet (--ack). It is kept so that it
+//! can be sent again after a reconnect, and it caps how far sending may run
+//! ahead of the server.
+//!
+//! Offsets count bytes of 32-bit float samples from the start of the
+//! session, whatever the wire encoding, so both ends agree on them.
+
+use std::collections::VecDeque;
+
+use crate::offline::Utterance;
+
+/// Bytes per sample in an offset
+const SAMPLE_BYTES: u64 = 4;
+
+pub struct AckWindow {
+    /// Offset after everything sent
+    sent: u64,
+    /// Offset the server has acknowledged up to
+    acked: u64,
+    max_unacked: u64,
+    /// Unacknowledged utterances with the offset each ends at
+    pending: VecDeque<(u64, Utterance)>,
+}
+
+impl AckWindow {
+    pub fn new(max_unacked: u64) -> Self {
+        Self {
+            sent: 0,
+            acked: 0,
+            max_unacked,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Whether --ack-window-kb is used up, so new speech has to wait.
+    pub fn is_full(&self) -> bool {
+        self.sent.saturating_sub(self.acked) >= self.max_unacked
+    }
+
+    /// Hold on to an utterance about to be sent until it is acknowledged.
+    pub fn sent(&mut self, utterance: Utterance) {
+        self.sent += utterance.audio.len() as u64 * SAMPLE_BYTES;
+        self.pending.push_back((self.sent, utterance));
+    }
+
+    /// The server has everything up to `offset`.
+    pub fn ack(&mut self, offset: u64) {
+        self.acked = offset;
+        while self.pending.front().is_some_and(|(end, _)| *end <= offset) {
+            self.pending.pop_front();
+        }
+    }
+
+    /// Start over from `offset`, the server's acknowledgment on connecting,
+    /// returning the utterances it never received, oldest first. The offset
+    /// is lower than expected when the server lost the session.
+    pub fn resume(&mut self, offset: u64) -> Vec<Utterance> {
+        self.ack(offset);
+        self.sent = offset;
+        self.pending
+            .drain(..)
+            .map(|(_, utterance)| utterance)
+            .collect()
+    }
+}
@@ -0,0 +1,189 @@
+//! Adapter for the Azure Speech service's realtime WebSocket protocol: each
+//! utterance is one turn, sent as audio messages under its own request ID,
+//! and the recognized phrases arrive before `turn.end`.
+
+use anyhow::{Context, Result};
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::{HeaderName, HeaderValue};
+use tokio_tungstenite::tungstenite::Message;
+use uuid::Uuid;
+
+use crate::connection::{self, ServerResponse, WsRead, WsStream, WsWrite};
+use crate::dsp;
+use crate::Args;
+
+/// The service expects 16kHz 16-bit mono PCM
+const SAMPLE_RATE: u32 = 16000;
+/// Bytes of audio per message, unless --frames-per-message
+const CHUNK_BYTES: usize = 8192;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Phrase {
+    recognition_status: String,
+    display_text: Option<String>,
+}
+
+fn timestamp() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
+
+/// Connect with the key from --api-key or AZURE_SPEECH_KEY to the region's
+/// endpoint (or --azure-endpoint) and send the speech config.
+pub async fn open(args: &Args) -> Result<WsStream> {
+    let key = args
+        .api_key
+        .clone()
+        .or_else(|| std::env::var("AZURE_SPEECH_KEY").ok())
+        .context("--backend azure needs --api-key or AZURE_SPEECH_KEY")?;
+    let endpoint = match (&args.azure_endpoint, &args.azure_region) {
+        (Some(endpoint), _) => endpoint.clone(),
+        (None, Some(region)) => format!(
+            "wss://{}.stt.speech.microsoft.com/speech/recognition/conversation/cognitiveservices/v1",
+            region
+        ),
+        (None, None) => anyhow::bail!("--backend azure needs --azure-region or --azure-endpoint"),
+    };
+    let mut url = url::Url::parse(&endpoint)?;
+    url.query_pairs_mut()
+        .append_pair("language", &args.azure_language)
+        .append_pair("format", "simple");
+
+    let mut request = url.as_str().into_client_request()?;
+    let headers = request.headers_mut();
+    headers.insert("ocp-apim-subscription-key", HeaderValue::from_str(&key)?);
+    headers.insert(
+        "x-connectionid",
+        HeaderValue::from_str(&Uuid::new_v4().simple().to_string())?,
+    );
+    for (name, value) in &args.headers {
+        headers.append(
+            HeaderName::from_bytes(name.as_bytes())?,
+            HeaderValue::from_str(value)?,
+        );
+    }
+    let (mut stream, _) = connection::connect(request, args).await?;
+
+    let config = json!({
+        "context": {
+            "system": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+            "os": { "platform": std::env::consts::OS },
+        },
+    });
+    let message = format!(
+        "Path: speech.config\r\nX-Timestamp: {}\r\nContent-Type: application/json\r\n\r\n{}",
+        timestamp(),
+        config
+    );
+    stream.send(Message::Text(message)).await?;
+    Ok(stream)
+}
+
+/// A binary audio message: big-endian header length, headers, then audio.
+fn audio_message(request_id: &str, body: &[u8]) -> Message {
+    let headers = format!(
+        "Path: audio\r\nX-RequestId: {}\r\nX-Timestamp: {}\r\nContent-Type: audio/x-wav\r\n",
+        request_id,
+        timestamp()
+    );
+    let mut message = Vec::with_capacity(2 + headers.len() + body.len());
+    message.extend_from_slice(&(headers.len() as u16).to_be_bytes());
+    message.extend_from_slice(headers.as_bytes());
+    message.extend_from_slice(body);
+    Message::Binary(message)
+}
+
+/// A canonical 44-byte WAV header, sent ahead of the first audio chunk.
+fn wav_header(data_len: u32) -> Vec<u8> {
+    let mut header = Vec::with_capacity(44);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&(36 + data_len).to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    header.extend_from_slice(&1u16.to_le_bytes()); // mono
+    header.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    header.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    header.extend_from_slice(&2u16.to_le_bytes());
+    header.extend_from_slice(&16u16.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_len.to_le_bytes());
+    header
+}
+
+/// Transcribe one utterance as a turn. An error means the connection is
+/// gone; a turn without a recognized phrase gives an empty transcript.
+pub async fn transcribe(
+    write: &mut WsWrite,
+    read: &mut WsRead,
+    audio: &[f32],
+    sample_rate: u32,
+    message_ms: Option<u32>,
+    send_timeout: Option<Duration>,
+) -> Result<Option<ServerResponse>> {
+    let request_id = Uuid::new_v4().simple().to_string();
+    // 16-bit samples
+    let chunk_bytes = connection::message_samples(message_ms, SAMPLE_RATE, CHUNK_BYTES / 2) * 2;
+    let audio = dsp::resample(audio, sample_rate, SAMPLE_RATE);
+    let pcm: Vec<u8> = dsp::f32_to_i16(&audio, false)
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .collect();
+    let mut first = wav_header(pcm.len() as u32);
+    let split = pcm.len().min(chunk_bytes);
+    first.extend_from_slice(&pcm[..split]);
+    connection::send_within(write, audio_message(&request_id, &first), send_timeout).await?;
+    for chunk in pcm[split..].chunks(chunk_bytes) {
+        connection::send_within(write, audio_message(&request_id, chunk), send_timeout).await?;
+    }
+    // An empty audio message ends the turn's audio
+    connection::send_within(write, audio_message(&request_id, &[]), send_timeout).await?;
+
+    let mut phrases = Vec::new();
+    loop {
+        let text = match read.next().await {
+            Some(Ok(Message::Text(text))) => text,
+            Some(Ok(Message::Close(_))) | None => anyhow::bail!("connection closed"),
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => return Err(e.into()),
+        };
+        let (headers, body) = text.split_once("\r\n\r\n").unwrap_or((&text, ""));
+        let header = |name: &str| {
+            headers.lines().find_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+            })
+        };
+        // Messages for an earlier turn, e.g. one abandoned by a timeout
+        if header("X-RequestId").is_some_and(|id| !id.eq_ignore_ascii_case(&request_id)) {
+            continue;
+        }
+        match header("Path") {
+            Some("speech.phrase") => {
+                if let Ok(phrase) = serde_json::from_str::<Phrase>(body) {
+                    if phrase.recognition_status == "Success" {
+                        phrases.extend(phrase.display_text);
+                    }
+                }
+            }
+            Some("turn.end") => {
+                return Ok(Some(ServerResponse {
+                    msg_type: "result".to_string(),
+                    text: Some(phrases.join(" ")),
+                    sample: None,
+                    message: None,
+                    utterance_id: None,
+                    words: Vec::new(),
+                    confidence: None,
+                    speaker: None,
+                }))
+            }
+            // turn.start, speech.startDetected, speech.hypothesis, ...
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! Reconnect delays: exponential backoff with jitter, so clients that lost
+//! the same server don't all retry in lockstep.
+
+use rand::Rng;
+use std::time::Duration;
+
+pub struct Backoff {
+    min_ms: u64,
+    max_ms: u64,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(min_ms: u64, max_ms: u64) -> Self {
+        Self {
+            min_ms: min_ms.max(1),
+            max_ms: max_ms.max(min_ms),
+            attempt: 0,
+        }
+    }
+
+    /// Delay before the next attempt: doubles per failed attempt up to the
+    /// cap, then is drawn uniformly from its upper half.
+    pub fn next_delay(&mut self) -> Duration {
+        let ceiling = self
+            .min_ms
+            .saturating_mul(1 << self.attempt.min(20))
+            .min(self.max_ms);
+        self.attempt += 1;
+        Duration::from_millis(rand::thread_rng().gen_range(ceiling / 2..=ceiling))
+    }
+
+    /// Start over after a successful connection.
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
@@ -0,0 +1,185 @@
+//! Fallback for when no streaming server is reachable (--batch-url): each
+//! utterance is POSTed to a REST endpoint as a WAV file and the reply is
+//! read like a WebSocket one, or as the bare transcript if it isn't JSON.
+
+use anyhow::{bail, Context, Result};
+use std::io::Cursor;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use url::Url;
+use uuid::Uuid;
+
+use crate::connection::{self, ServerResponse};
+use crate::Args;
+
+/// Largest response read
+const MAX_RESPONSE: u64 = 1 << 20;
+
+/// POST one utterance to `url` and return its transcript.
+pub async fn transcribe(
+    url: &str,
+    args: &Args,
+    audio: &[f32],
+    sample_rate: u32,
+    utterance_id: Uuid,
+) -> Result<Option<ServerResponse>> {
+    let mut url = Url::parse(url).with_context(|| format!("Invalid --batch-url {}", url))?;
+    let credential = args.token.as_ref().or(args.api_key.as_ref());
+    if let (Some(name), Some(credential)) = (&args.auth_query, credential) {
+        url.query_pairs_mut().append_pair(name, credential);
+    }
+    if !matches!(url.scheme(), "http" | "https") {
+        bail!("--batch-url must be http or https, not {}", url.scheme());
+    }
+    let mut headers = format!(
+        "Content-Type: audio/wav\r\nX-Utterance-Id: {}\r\n",
+        utterance_id
+    );
+    for (name, value) in connection::handshake_headers(args) {
+        headers.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    let body = wav(audio, sample_rate)?;
+    let response = post(
+        &url,
+        args.proxy.as_deref(),
+        args.tls.as_ref(),
+        &headers,
+        &body,
+    )
+    .await?;
+    parse(&response, args)
+}
+
+/// POST `body` to an http or https URL and return the whole response.
+/// `headers` are extra header lines, each ending in CRLF.
+pub async fn post(
+    url: &Url,
+    proxy: Option<&str>,
+    tls: Option<&native_tls::TlsConnector>,
+    headers: &str,
+    body: &[u8],
+) -> Result<Vec<u8>> {
+    let https = url.scheme() == "https";
+    let host = url
+        .host_str()
+        .with_context(|| format!("{} has no host", url))?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let port = url.port_or_known_default().unwrap_or(80);
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+    // HTTP/1.0 so the reply comes unchunked and ends when the server closes
+    let head = format!(
+        "POST {} HTTP/1.0\r\nHost: {}\r\n{}Content-Length: {}\r\n\r\n",
+        path,
+        url.authority(),
+        headers,
+        body.len()
+    );
+
+    let scheme = if https { "wss" } else { "ws" };
+    let stream = match crate::proxy::for_target(scheme, &host, proxy)? {
+        Some(proxy) => crate::proxy::tunnel(&proxy, &host, port).await?,
+        None => TcpStream::connect((host.as_str(), port))
+            .await
+            .with_context(|| format!("Cannot reach {}", url.authority()))?,
+    };
+    if https {
+        let connector = match tls {
+            Some(connector) => connector.clone(),
+            None => native_tls::TlsConnector::new()?,
+        };
+        let stream = tokio_native_tls::TlsConnector::from(connector)
+            .connect(&host, stream)
+            .await?;
+        exchange(stream, head.as_bytes(), body).await
+    } else {
+        exchange(stream, head.as_bytes(), body).await
+    }
+}
+
+/// Send the request and read the whole response.
+async fn exchange<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    head: &[u8],
+    body: &[u8],
+) -> Result<Vec<u8>> {
+    stream.write_all(head).await?;
+    stream.write_all(body).await?;
+    stream.flush().await?;
+    let mut response = Vec::new();
+    stream.take(MAX_RESPONSE).read_to_end(&mut response).await?;
+    Ok(response)
+}
+
+/// The body of an HTTP response, or an error with its status line if it
+/// isn't a success.
+pub fn checked_body(response: &[u8]) -> Result<String> {
+    let split = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .context("Malformed HTTP response")?;
+    let head = String::from_utf8_lossy(&response[..split]);
+    let body = String::from_utf8_lossy(&response[split + 4..]);
+    let status = head.lines().next().unwrap_or_default();
+    if !status
+        .split_whitespace()
+        .nth(1)
+        .is_some_and(|code| code.starts_with('2'))
+    {
+        bail!("{}", status);
+    }
+    Ok(body.trim().to_string())
+}
+
+/// The transcript in an HTTP response, after checking its status.
+fn parse(response: &[u8], args: &Args) -> Result<Option<ServerResponse>> {
+    let body = checked_body(response)?;
+    let body = body.as_str();
+    if let Some(mapping) = &args.config.response {
+        return Ok(crate::mapping::map_reply(mapping, body));
+    }
+    let text = if body.starts_with('{') {
+        if let Ok(reply) = serde_json::from_str::<ServerResponse>(body) {
+            return Ok(Some(reply));
+        }
+        // No type, e.g. OpenAI's `{"text": "..."}`
+        let value: serde_json::Value = serde_json::from_str(body)?;
+        match value.get("text").and_then(|t| t.as_str()) {
+            Some(text) => text.to_string(),
+            None => return Ok(None),
+        }
+    } else {
+        body.to_string()
+    };
+    Ok(Some(ServerResponse {
+        msg_type: "result".to_string(),
+        text: Some(text),
+        sample: None,
+        message: None,
+        utterance_id: None,
+        words: Vec::new(),
+        confidence: None,
+        speaker: None,
+    }))
+}
+
+/// 16-bit mono WAV, which every transcription API accepts.
+fn wav(audio: &[f32], sample_rate: u32) -> Result<Vec<u8>> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut cursor = Cursor::new(Vec::new());
+    let mut writer = hound::WavWriter::new(&mut cursor, spec)?;
+    for &s in audio {
+        writer.write_sample((s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?;
+    }
+    writer.finalize()?;
+    Ok(cursor.into_inner())
+}
@@ -0,0 +1,48 @@
+//! Learns the speaker's pause lengths between words to set how much silence
+//! ends an utterance.
+
+use std::collections::VecDeque;
+
+pub struct Cadence {
+    pauses: VecDeque<u32>,
+    silence_ms: u32,
+    min_ms: u32,
+    max_ms: u32,
+}
+
+impl Cadence {
+    /// Pauses remembered, covering roughly the last few sentences
+    const HISTORY: usize = 50;
+    /// Pauses needed before the threshold moves from its initial value
+    const MIN_PAUSES: usize = 5;
+    /// End-of-utterance silence relative to a long inter-word pause
+    const MULTIPLIER: f32 = 2.5;
+
+    pub fn new(initial_ms: u32, min_ms: u32, max_ms: u32) -> Self {
+        Self {
+            pauses: VecDeque::with_capacity(Self::HISTORY),
+            silence_ms: initial_ms.clamp(min_ms, max_ms),
+            min_ms,
+            max_ms,
+        }
+    }
+
+    /// Record a pause inside an utterance, i.e. silence followed by more speech.
+    pub fn record_pause(&mut self, ms: u32) {
+        if self.pauses.len() == Self::HISTORY {
+            self.pauses.pop_front();
+        }
+        self.pauses.push_back(ms);
+        if self.pauses.len() >= Self::MIN_PAUSES {
+            let mut sorted: Vec<u32> = self.pauses.iter().copied().collect();
+            sorted.sort_unstable();
+            let p90 = sorted[(sorted.len() - 1) * 9 / 10];
+            self.silence_ms =
+                ((p90 as f32 * Self::MULTIPLIER) as u32).clamp(self.min_ms, self.max_ms);
+        }
+    }
+
+    pub fn silence_ms(&self) -> u32 {
+        self.silence_ms
+    }
+}
@@ -0,0 +1,67 @@
+use anyhow::Result;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::dsp::{calculate_energy, db_to_linear, f32_to_i16};
+use crate::frontend::Frontend;
+use crate::vad::VadMode;
+
+/// Thresholds chosen from a stretch of ambient room sound.
+pub struct Calibration {
+    pub min_energy: f32,
+    pub vad_mode: VadMode,
+    pub noise_rms: f32,
+}
+
+/// Fraction of ambient chunks the VAD may flag as speech.
+const MAX_FALSE_POSITIVE_RATE: f32 = 0.05;
+/// Lower bound on the chosen threshold, for digitally silent inputs.
+const MIN_ENERGY_FLOOR: f32 = 0.001;
+
+/// Measure ambient noise for `duration` and pick the energy threshold (loud
+/// ambient chunks + margin) and the least aggressive VAD mode that stays
+/// quiet on it.
+pub async fn run(
+    frontend: &mut Frontend,
+    audio_rx: &mut mpsc::Receiver<Vec<f32>>,
+    duration: Duration,
+    sample_rate: u32,
+    margin_db: f32,
+    dither: bool,
+) -> Result<Calibration> {
+    let wanted = (duration.as_secs_f32() * sample_rate as f32) as usize;
+    let mut chunks: Vec<Vec<f32>> = Vec::new();
+    while chunks.iter().map(Vec::len).sum::<usize>() < wanted {
+        let Some(samples) = audio_rx.recv().await else {
+            break;
+        };
+        chunks.extend(frontend.push(&samples)?);
+    }
+
+    let mut energies: Vec<f32> = chunks.iter().map(|c| calculate_energy(c)).collect();
+    energies.sort_by(f32::total_cmp);
+    // 90th percentile, so occasional bumps don't dominate
+    let noise_rms = energies
+        .get(energies.len() * 9 / 10)
+        .copied()
+        .unwrap_or_default();
+
+    let frames: Vec<Vec<i16>> = chunks.iter().map(|c| f32_to_i16(c, dither)).collect();
+    let vad_mode = VadMode::ALL
+        .into_iter()
+        .find(|mode| {
+            let mut vad = mode.create_vad();
+            let voiced = frames
+                .iter()
+                .filter(|f| vad.is_voice_segment(f).unwrap_or(false))
+                .count();
+            (voiced as f32) <= MAX_FALSE_POSITIVE_RATE * frames.len() as f32
+        })
+        .unwrap_or(VadMode::VeryAggressive);
+
+    Ok(Calibration {
+        min_energy: (noise_rms * db_to_linear(margin_db)).max(MIN_ENERGY_FLOOR),
+        vad_mode,
+        noise_rms,
+    })
+}
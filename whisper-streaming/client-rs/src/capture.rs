@@ -0,0 +1,107 @@
+use anyhow::{bail, Context, Result};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+use crate::config::downmix_weights;
+
+/// Live microphone capture. Samples are delivered at the device's native
+/// rate; the stream stays open for as long as this value is alive.
+pub struct Capture {
+    _stream: cpal::Stream,
+    running: Arc<AtomicBool>,
+    pub sample_rate: u32,
+    pub device_name: String,
+}
+
+impl Capture {
+    /// Open the named input device (substring match), or the default one.
+    /// Multichannel input is downmixed to mono with `channel_weights`.
+    pub fn start(
+        device_name: Option<&str>,
+        channel_weights: Option<&[f32]>,
+    ) -> Result<(Self, mpsc::Receiver<Vec<f32>>)> {
+        let (audio_tx, audio_rx) = mpsc::channel::<Vec<f32>>(100);
+
+        let host = cpal::default_host();
+        let device = match device_name {
+            Some(name) => find_input_device(&host, name)?,
+            None => host
+                .default_input_device()
+                .context("No input device available")?,
+        };
+
+        let device_name = device.name().unwrap_or_default();
+        let default_config = device.default_input_config()?;
+        let sample_rate = default_config.sample_rate().0;
+        let channels = default_config.channels() as usize;
+        let weights = downmix_weights(channel_weights, channels)?;
+        if channels > 1 {
+            say!(
+                "Input channels: {} (downmix weights {:?})",
+                channels,
+                weights
+            );
+        }
+
+        let config = cpal::StreamConfig {
+            channels: channels as u16,
+            sample_rate: cpal::SampleRate(sample_rate),
+            buffer_size: cpal::BufferSize::Default,
+        };
+
+        let running = Arc::new(AtomicBool::new(true));
+        let running_clone = running.clone();
+
+        let stream = device.build_input_stream(
+            &config,
+            move |data: &[f32], _: &cpal::InputCallbackInfo| {
+                if running_clone.load(Ordering::Relaxed) {
+                    let mono = if channels == 1 {
+                        data.to_vec()
+                    } else {
+                        data.chunks_exact(channels)
+                            .map(|frame| frame.iter().zip(&weights).map(|(s, w)| s * w).sum())
+                            .collect()
+                    };
+                    let _ = audio_tx.blocking_send(mono);
+                }
+            },
+            |err| eprintln!("Audio error: {}", err),
+            None,
+        )?;
+
+        stream.play()?;
+
+        Ok((
+            Self {
+                _stream: stream,
+                running,
+                sample_rate,
+                device_name,
+            },
+            audio_rx,
+        ))
+    }
+
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+fn find_input_device(host: &cpal::Host, name: &str) -> Result<cpal::Device> {
+    let mut available = Vec::new();
+    for device in host.input_devices()? {
+        let device_name = device.name().unwrap_or_default();
+        if device_name.contains(name) {
+            return Ok(device);
+        }
+        available.push(device_name);
+    }
+    bail!(
+        "No input device matching '{}' (available: {})",
+        name,
+        available.join(", ")
+    )
+}
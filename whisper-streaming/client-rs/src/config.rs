@@ -0,0 +1,186 @@
+//! Optional TOML configuration for settings that don't fit on the command line.
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub capture: CaptureConfig,
+    /// Ordered preprocessing chain; replaces the one built from flags
+    pub dsp: Option<Vec<StageConfig>>,
+    /// Where a third-party server's JSON replies keep the fields the client
+    /// reads
+    pub response: Option<ResponseConfig>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptureConfig {
+    /// Downmix weight per input channel; defaults to an equal average
+    pub channel_weights: Option<Vec<f32>>,
+    /// Per-device overrides, keyed by the name given to `--device`
+    pub device: HashMap<String, DeviceConfig>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviceConfig {
+    pub channel_weights: Option<Vec<f32>>,
+}
+
+/// Field selectors for server replies, e.g. `text =
+/// "results[0].alternatives[0].transcript"`. Each defaults to the field of
+/// the same name in the whisper server's own replies.
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResponseConfig {
+    /// The message type, renamed through `types`
+    #[serde(rename = "type")]
+    pub msg_type: Option<String>,
+    /// A boolean telling finals from partials, for replies without a type
+    pub is_final: Option<String>,
+    pub text: Option<String>,
+    /// Rejected text of a `noise` reply
+    pub sample: Option<String>,
+    /// Detail of `status`, `warning` and `error` messages
+    pub message: Option<String>,
+    pub utterance_id: Option<String>,
+    /// Speaker ID from a diarizing server, a string or number
+    pub speaker: Option<String>,
+    /// Server type names for the client's `result`, `partial`, `noise`,
+    /// `status`, `warning` and `error`, e.g. `Final = "result"`
+    pub types: HashMap<String, String>,
+}
+
+/// One preprocessing stage, e.g. `[[dsp]] stage = "highpass"` with
+/// `cutoff_hz = 80`. Omitted parameters take the flag defaults.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "stage", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum StageConfig {
+    /// Echo cancellation; only active while the client plays audio
+    Aec {
+        #[serde(default = "default_aec_tail_ms")]
+        tail_ms: u32,
+    },
+    Highpass {
+        #[serde(default = "default_highpass_hz")]
+        cutoff_hz: f32,
+    },
+    Lowpass {
+        cutoff_hz: f32,
+    },
+    /// 300-3400Hz telephone band
+    VoiceBand,
+    Gain {
+        db: f32,
+    },
+    /// Late-reverb suppression for echoey rooms
+    Dereverb {
+        #[serde(default = "default_rt60_ms")]
+        rt60_ms: f32,
+    },
+    SpectralSubtraction {
+        profile: PathBuf,
+    },
+    Denoise,
+    Agc {
+        #[serde(default = "default_agc_target")]
+        target: f32,
+        #[serde(default = "default_agc_floor")]
+        floor: f32,
+        #[serde(default = "default_agc_max_gain")]
+        max_gain_db: f32,
+    },
+    Limiter {
+        #[serde(default = "default_limiter_threshold")]
+        threshold: f32,
+    },
+    /// Shared library implementing the DSP plugin ABI (see `dsp::Plugin`)
+    Plugin {
+        path: PathBuf,
+    },
+}
+
+fn default_aec_tail_ms() -> u32 {
+    128
+}
+
+fn default_highpass_hz() -> f32 {
+    80.0
+}
+
+fn default_rt60_ms() -> f32 {
+    500.0
+}
+
+fn default_agc_target() -> f32 {
+    0.05
+}
+
+fn default_agc_floor() -> f32 {
+    0.002
+}
+
+fn default_agc_max_gain() -> f32 {
+    30.0
+}
+
+fn default_limiter_threshold() -> f32 {
+    0.9
+}
+
+impl fmt::Display for StageConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StageConfig::Aec { tail_ms } => write!(f, "aec({}ms)", tail_ms),
+            StageConfig::Highpass { cutoff_hz } => write!(f, "highpass({}Hz)", cutoff_hz),
+            StageConfig::Lowpass { cutoff_hz } => write!(f, "lowpass({}Hz)", cutoff_hz),
+            StageConfig::VoiceBand => write!(f, "voice-band"),
+            StageConfig::Gain { db } => write!(f, "gain({:+.1}dB)", db),
+            StageConfig::Dereverb { rt60_ms } => write!(f, "dereverb(RT60 {}ms)", rt60_ms),
+            StageConfig::SpectralSubtraction { profile } => {
+                write!(f, "spectral-subtraction({})", profile.display())
+            }
+            StageConfig::Denoise => write!(f, "denoise"),
+            StageConfig::Agc {
+                target,
+                max_gain_db,
+                ..
+            } => write!(f, "agc({}, max +{}dB)", target, max_gain_db),
+            StageConfig::Limiter { threshold } => write!(f, "limiter({})", threshold),
+            StageConfig::Plugin { path } => write!(f, "plugin({})", path.display()),
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read config {}", path.display()))?;
+        toml::from_str(&data).with_context(|| format!("Invalid config {}", path.display()))
+    }
+
+    pub fn channel_weights(&self, device: Option<&str>) -> Option<&[f32]> {
+        device
+            .and_then(|d| self.capture.device.get(d))
+            .and_then(|d| d.channel_weights.as_deref())
+            .or(self.capture.channel_weights.as_deref())
+    }
+}
+
+/// Resolve downmix weights for a device with `channels` inputs.
+pub fn downmix_weights(weights: Option<&[f32]>, channels: usize) -> Result<Vec<f32>> {
+    match weights {
+        None => Ok(vec![1.0 / channels as f32; channels]),
+        Some(w) if w.len() == channels => Ok(w.to_vec()),
+        Some(w) => bail!(
+            "Device has {} input channels but {} channel weights were configured",
+            channels,
+            w.len()
+        ),
+    }
+}
@@ -0,0 +1,1680 @@
+//! Server connection: handshake, audio message encoding and replies.
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+use prost::Message as _;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::client::{Request, Response};
+use tokio_tungstenite::tungstenite::http::header::{
+    SEC_WEBSOCKET_EXTENSIONS, SEC_WEBSOCKET_PROTOCOL,
+};
+use tokio_tungstenite::tungstenite::http::{HeaderName, HeaderValue};
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::{client_async_with_config, MaybeTlsStream, WebSocketStream};
+use uuid::Uuid;
+
+use crate::config::ResponseConfig;
+use crate::deflate::{self, Deflate};
+use crate::proto::{
+    self, client_message::Message as ClientBody, server_message::Message as ServerBody,
+};
+use crate::socket::{Socket, UnixPath};
+use crate::Args;
+
+/// How long to wait for a `config_ack`; older servers never send one
+const CONFIG_ACK_TIMEOUT: Duration = Duration::from_secs(2);
+/// WebSocket subprotocols under which the server accepts binary audio frames
+const PCM_PROTOCOL: &str = "whisper-pcm.v1";
+const OPUS_PROTOCOL: &str = "whisper-opus.v1";
+/// Subprotocol for MessagePack messages in both directions
+const MSGPACK_PROTOCOL: &str = "whisper-msgpack.v1";
+/// Subprotocol for the messages of `proto/whisper.proto`
+const PROTOBUF_PROTOCOL: &str = "whisper-protobuf.v1";
+const BINARY_MAGIC: &[u8; 4] = b"WPCM";
+
+/// How audio is sent to the server.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WireFormat {
+    /// Base64 float32 samples in a JSON message; works with any server
+    Json,
+    /// Raw samples in binary frames, when the server supports them
+    Binary,
+    /// MessagePack messages with raw sample bytes, replies included
+    Msgpack,
+    /// Protobuf messages per `proto/whisper.proto`, replies included
+    Protobuf,
+}
+
+/// Sample encoding of binary audio frames.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleFormat {
+    F32,
+    I16,
+}
+
+/// Compression applied to outgoing audio.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    /// Uncompressed samples
+    Pcm,
+    /// Opus in VoIP mode (needs the `opus` feature)
+    Opus,
+}
+
+/// What actually goes over the wire, once negotiated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Json,
+    Pcm(SampleFormat),
+    Opus { bitrate: u32 },
+    Msgpack,
+    Protobuf(SampleFormat),
+}
+
+impl Encoding {
+    fn subprotocol(self) -> Option<&'static str> {
+        match self {
+            Encoding::Json => None,
+            Encoding::Pcm(_) => Some(PCM_PROTOCOL),
+            Encoding::Opus { .. } => Some(OPUS_PROTOCOL),
+            Encoding::Msgpack => Some(MSGPACK_PROTOCOL),
+            Encoding::Protobuf(_) => Some(PROTOBUF_PROTOCOL),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Encoding::Json => write!(f, "JSON"),
+            Encoding::Pcm(format) => write!(f, "binary {:?}", format),
+            Encoding::Opus { bitrate } => write!(f, "Opus {}kbps", bitrate / 1000),
+            Encoding::Msgpack => write!(f, "MessagePack"),
+            Encoding::Protobuf(format) => write!(f, "protobuf ({:?} samples)", format),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct TranscribeMessage {
+    #[serde(rename = "type")]
+    msg_type: &'static str,
+    audio: String,
+    sample_rate: u32,
+    utterance_id: Uuid,
+    /// Audio so far of an utterance still in progress
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    partial: bool,
+}
+
+/// Ask for a session's results on the connection this is sent on.
+#[derive(Serialize)]
+struct SubscribeMessage {
+    #[serde(rename = "type")]
+    msg_type: &'static str,
+    session_id: Uuid,
+}
+
+#[derive(Serialize)]
+struct ResumeMessage {
+    #[serde(rename = "type")]
+    msg_type: &'static str,
+    session_id: Uuid,
+    last_utterance_id: Option<Uuid>,
+    /// Ask for `ack` messages (--ack)
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    ack: bool,
+}
+
+/// The server's count of audio received in the session, see `crate::ack`.
+#[derive(Deserialize)]
+struct AckMessage {
+    #[serde(rename = "type")]
+    msg_type: String,
+    offset: u64,
+}
+
+/// The offset in an `ack` message.
+fn ack_offset(text: &str) -> Option<u64> {
+    serde_json::from_str::<AckMessage>(text)
+        .ok()
+        .filter(|ack| ack.msg_type == "ack")
+        .map(|ack| ack.offset)
+}
+
+/// The transcript a client builds across connections, so the server can
+/// keep its context after a reconnect.
+pub struct Session {
+    pub id: Uuid,
+    /// Utterance of the last result received
+    pub last_utterance_id: Option<Uuid>,
+    /// Capture device, for the client metadata
+    pub device: Option<String>,
+}
+
+impl Session {
+    pub fn new(device: Option<String>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            last_utterance_id: None,
+            device,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ConfigMessage<'a> {
+    #[serde(rename = "type")]
+    msg_type: &'static str,
+    sample_rate: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<&'a str>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    hotwords: &'a [String],
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    word_timestamps: bool,
+    client: ClientMetadata<'a>,
+}
+
+/// Who is connecting and how it is set up, for correlating server-side
+/// quality issues with client configurations.
+#[derive(Serialize)]
+struct ClientMetadata<'a> {
+    name: &'static str,
+    version: &'static str,
+    os: &'static str,
+    arch: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device: Option<&'a str>,
+    encoding: String,
+    params: serde_json::Value,
+}
+
+/// The server's `config_ack`: what it settled on and what it supports.
+#[derive(Deserialize)]
+pub struct ServerInfo {
+    #[serde(rename = "type")]
+    msg_type: String,
+    pub model: Option<String>,
+    pub language: Option<String>,
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ServerResponse {
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    pub text: Option<String>,
+    pub sample: Option<String>,
+    /// Human-readable detail of `status`, `warning` and `error` messages
+    pub message: Option<String>,
+    /// The utterance a reply is for, when the server says
+    pub utterance_id: Option<String>,
+    /// Word timings, with --word-timestamps
+    #[serde(default)]
+    pub words: Vec<Word>,
+    /// How sure the server is of the whole transcript, 0 to 1
+    #[serde(default)]
+    pub confidence: Option<f64>,
+    /// Who said it, from a diarizing server
+    #[serde(default, deserialize_with = "speaker_id")]
+    pub speaker: Option<String>,
+}
+
+/// A speaker ID, which servers send as a string or a number.
+fn speaker_id<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<String>, D::Error> {
+    Ok(match Option::<serde_json::Value>::deserialize(d)? {
+        Some(serde_json::Value::String(id)) => Some(id),
+        Some(serde_json::Value::Number(id)) => Some(id.to_string()),
+        _ => None,
+    })
+}
+
+/// A word and when it was said, in seconds from the start of the utterance.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Word {
+    pub word: String,
+    pub start: f64,
+    pub end: f64,
+    /// 0 to 1, when the server says
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub probability: Option<f64>,
+}
+
+impl ServerResponse {
+    /// Whether this is a `status`, `warning` or `error` message rather than
+    /// a reply to the utterance.
+    pub fn is_notice(&self) -> bool {
+        matches!(self.msg_type.as_str(), "status" | "warning" | "error")
+    }
+
+    /// Whether this is for an utterance other than `utterance_id`, e.g. the
+    /// second reply to one that was sent again.
+    pub fn is_stale(&self, utterance_id: Uuid) -> bool {
+        self.utterance_id
+            .as_deref()
+            .is_some_and(|id| !id.is_empty() && id != utterance_id.to_string())
+    }
+
+    /// The client's own notice for an utterance with no reply in `timeout`:
+    /// `retry` when it is about to be sent again, `lost` once that went
+    /// unanswered too.
+    pub fn unanswered(utterance_id: Uuid, timeout: Duration, retried: bool) -> Self {
+        let (msg_type, message) = if retried {
+            (
+                "lost",
+                format!("No transcript for {} after a retry", utterance_id),
+            )
+        } else {
+            (
+                "retry",
+                format!(
+                    "No transcript for {} within {}ms, sending it again",
+                    utterance_id,
+                    timeout.as_millis()
+                ),
+            )
+        };
+        Self {
+            msg_type: msg_type.to_string(),
+            text: None,
+            sample: None,
+            message: Some(message),
+            utterance_id: Some(utterance_id.to_string()),
+            words: Vec::new(),
+            confidence: None,
+            speaker: None,
+        }
+    }
+}
+
+/// Set aside notices among the replies: `None` to keep waiting after a
+/// status or warning, `Some(None)` after an error, else the reply itself.
+pub fn screen(
+    notices: &mut Vec<ServerResponse>,
+    reply: Option<ServerResponse>,
+) -> Option<Option<ServerResponse>> {
+    match reply {
+        Some(reply) if reply.is_notice() => {
+            let error = reply.msg_type == "error";
+            notices.push(reply);
+            error.then_some(None)
+        }
+        reply => Some(reply),
+    }
+}
+
+pub type WsStream = WebSocketStream<Deflate<MaybeTlsStream<Socket>>>;
+pub type WsWrite = SplitSink<WsStream, Message>;
+pub type WsRead = SplitStream<WsStream>;
+/// Either end a WebSocket's results can arrive on
+type Replies = dyn Stream<Item = tungstenite::Result<Message>> + Unpin + Send;
+
+/// The speech-to-text service on the other end.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// The whisper-streaming server at --server-url
+    Whisper,
+    /// OpenAI's realtime transcription API at --openai-url
+    Openai,
+    /// Google Cloud Speech-to-Text v2 (needs the `google` feature)
+    Google,
+    /// Azure Speech service in --azure-region
+    Azure,
+    /// whisper.cpp in this process with --model-path (needs the `local`
+    /// feature)
+    Local,
+    /// A Vosk (Kaldi) WebSocket server at --server-url, e.g.
+    /// `ws://localhost:2700`
+    Vosk,
+}
+
+/// Which of several --server-url servers is used.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ServerSelection {
+    /// The first reachable one, returning to earlier ones when they recover
+    Order,
+    /// The one with the fastest handshake, re-probed every --probe-secs
+    Latency,
+}
+
+/// How the client reaches the server.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransportKind {
+    /// `<server-url>/ws/transcribe`
+    Websocket,
+    /// `whisper.v1.Transcriber/StreamingRecognize` at the server URL (needs
+    /// the `grpc` feature)
+    Grpc,
+    /// Opus over WebRTC, negotiated at `<server-url>/ws/webrtc` (needs the
+    /// `webrtc` feature)
+    Webrtc,
+    /// Experimental: a stream per utterance over QUIC at
+    /// `<server-url>/wt/transcribe`, an `https://` URL (needs the
+    /// `webtransport` feature)
+    Webtransport,
+    /// A ZeroMQ DEALER socket connected to the server URL, e.g.
+    /// `tcp://localhost:5555` (needs the `zmq` feature)
+    Zmq,
+}
+
+enum Transport {
+    WebSocket {
+        write: WsWrite,
+        read: WsRead,
+        /// Where results arrive with --results-url, instead of `read`
+        results: Option<Box<WsStream>>,
+    },
+    #[cfg(feature = "grpc")]
+    Grpc(Box<crate::grpc::GrpcStream>),
+    #[cfg(feature = "webrtc")]
+    Webrtc(Box<crate::rtc::RtcStream>),
+    #[cfg(feature = "webtransport")]
+    Quic(Box<crate::webtransport::WtStream>),
+    #[cfg(feature = "zmq")]
+    Zmq(Box<crate::zmq::ZmqStream>),
+    #[cfg(feature = "google")]
+    Google(Box<crate::google::GoogleStt>),
+    #[cfg(feature = "local")]
+    Local(Box<crate::local::LocalWhisper>),
+}
+
+pub struct Connection {
+    transport: Transport,
+    encoding: Encoding,
+    /// What --codec / --wire-format asked for
+    wanted: Encoding,
+    /// Index into --server-url
+    server: usize,
+    backend: Backend,
+    /// What the server said about itself in reply to `configure`
+    info: Option<ServerInfo>,
+    /// --send-timeout-ms for WebSocket sends
+    send_timeout: Option<Duration>,
+    /// --final-timeout-ms before an utterance is sent again
+    final_timeout: Option<Duration>,
+    /// Audio per message to the backends that stream it, from
+    /// --frames-per-message
+    message_ms: Option<u32>,
+    /// Whether `resume` asks for acknowledgments
+    acks: bool,
+    /// The last acknowledged offset not yet taken
+    acked: Option<u64>,
+    /// `[response]` selectors for a third-party server's replies
+    mapping: Option<ResponseConfig>,
+    /// `status`, `warning` and `error` messages not yet shown
+    notices: Vec<ServerResponse>,
+}
+
+impl Connection {
+    /// Connect to the first reachable server of `--server-url`.
+    pub async fn open(args: &Args) -> Result<Self> {
+        Self::open_from(args, 0).await
+    }
+
+    /// Try the servers in order starting at index `first`, wrapping around.
+    pub async fn open_from(args: &Args, first: usize) -> Result<Self> {
+        let count = args.server_urls.len();
+        let mut last_error = anyhow::anyhow!("No server URL");
+        for i in 0..count {
+            match Self::open_server(args, (first + i) % count).await {
+                Ok(conn) => return Ok(conn),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Connect to server `server` of `--server-url`.
+    pub async fn open_server(args: &Args, server: usize) -> Result<Self> {
+        let mut conn = Self::open_transport(args, server).await?;
+        conn.send_timeout =
+            (args.send_timeout_ms > 0).then(|| Duration::from_millis(args.send_timeout_ms));
+        conn.final_timeout =
+            (args.final_timeout_ms > 0).then(|| Duration::from_millis(args.final_timeout_ms));
+        conn.message_ms = args.frames_per_message.map(|n| n * args.chunk_ms);
+        conn.acks = args.ack;
+        conn.mapping = args.config.response.clone();
+        Ok(conn)
+    }
+
+    async fn open_transport(args: &Args, server: usize) -> Result<Self> {
+        match args.backend {
+            Backend::Whisper => {}
+            Backend::Openai => {
+                let stream = crate::openai::open(args).await?;
+                return Ok(Self {
+                    backend: Backend::Openai,
+                    server,
+                    ..Self::websocket(stream, Encoding::Json, Encoding::Json, None)
+                });
+            }
+            Backend::Vosk => {
+                let request = handshake_request(&args.server_urls[server], args)?;
+                let (mut stream, _) = connect(request, args).await?;
+                crate::vosk::configure(&mut stream, args.sample_rate).await?;
+                return Ok(Self {
+                    backend: Backend::Vosk,
+                    server,
+                    ..Self::websocket(stream, Encoding::Json, Encoding::Json, None)
+                });
+            }
+            Backend::Azure => {
+                let stream = crate::azure::open(args).await?;
+                return Ok(Self {
+                    backend: Backend::Azure,
+                    server,
+                    ..Self::websocket(stream, Encoding::Json, Encoding::Json, None)
+                });
+            }
+            #[cfg(feature = "google")]
+            Backend::Google => {
+                let stt = crate::google::GoogleStt::open(args).await?;
+                return Ok(Self::with_transport(
+                    Transport::Google(Box::new(stt)),
+                    Backend::Google,
+                    server,
+                ));
+            }
+            #[cfg(not(feature = "google"))]
+            Backend::Google => anyhow::bail!("Built without Google Speech-to-Text support"),
+            #[cfg(feature = "local")]
+            Backend::Local => {
+                let model = args
+                    .model_path
+                    .as_deref()
+                    .context("--backend local needs --model-path")?;
+                let whisper = crate::local::LocalWhisper::open(model, args.local_language.clone())?;
+                return Ok(Self::with_transport(
+                    Transport::Local(Box::new(whisper)),
+                    Backend::Local,
+                    server,
+                ));
+            }
+            #[cfg(not(feature = "local"))]
+            Backend::Local => anyhow::bail!("Built without whisper.cpp support"),
+        }
+        let url = &args.server_urls[server];
+        Ok(match args.transport {
+            TransportKind::Websocket => {
+                let results = args
+                    .results_urls
+                    .get(server)
+                    .map(|u| format!("{}/ws/results", u));
+                let url = format!("{}/ws/transcribe", url);
+                Self {
+                    server,
+                    ..Self::open_websocket(&url, results.as_deref(), args).await?
+                }
+            }
+            #[cfg(feature = "grpc")]
+            TransportKind::Grpc => {
+                let encoding = Encoding::Protobuf(args.pcm);
+                let stream = crate::grpc::GrpcStream::open(
+                    url,
+                    handshake_headers(args),
+                    ping_settings(args),
+                )
+                .await?;
+                let transport = Transport::Grpc(Box::new(stream));
+                Self {
+                    encoding,
+                    wanted: encoding,
+                    ..Self::with_transport(transport, Backend::Whisper, server)
+                }
+            }
+            #[cfg(not(feature = "grpc"))]
+            TransportKind::Grpc => anyhow::bail!("Built without gRPC support"),
+            #[cfg(feature = "webrtc")]
+            TransportKind::Webrtc => {
+                let encoding = Encoding::Opus {
+                    bitrate: args.opus_bitrate,
+                };
+                let stream =
+                    crate::rtc::RtcStream::open(&format!("{}/ws/webrtc", url), args).await?;
+                Self {
+                    encoding,
+                    wanted: encoding,
+                    ..Self::with_transport(
+                        Transport::Webrtc(Box::new(stream)),
+                        Backend::Whisper,
+                        server,
+                    )
+                }
+            }
+            #[cfg(not(feature = "webrtc"))]
+            TransportKind::Webrtc => anyhow::bail!("Built without WebRTC support"),
+            #[cfg(feature = "webtransport")]
+            TransportKind::Webtransport => {
+                // One message per stream
+                let encoding = single_message_encoding(args);
+                let stream =
+                    crate::webtransport::WtStream::open(&format!("{}/wt/transcribe", url), args)
+                        .await?;
+                Self {
+                    encoding,
+                    wanted: encoding,
+                    ..Self::with_transport(
+                        Transport::Quic(Box::new(stream)),
+                        Backend::Whisper,
+                        server,
+                    )
+                }
+            }
+            #[cfg(not(feature = "webtransport"))]
+            TransportKind::Webtransport => anyhow::bail!("Built without WebTransport support"),
+            #[cfg(feature = "zmq")]
+            TransportKind::Zmq => {
+                let encoding = single_message_encoding(args);
+                let stream = crate::zmq::ZmqStream::open(url).await?;
+                Self {
+                    encoding,
+                    wanted: encoding,
+                    ..Self::with_transport(
+                        Transport::Zmq(Box::new(stream)),
+                        Backend::Whisper,
+                        server,
+                    )
+                }
+            }
+            #[cfg(not(feature = "zmq"))]
+            TransportKind::Zmq => anyhow::bail!("Built without ZeroMQ support"),
+        })
+    }
+
+    /// Connect to every server at once and keep the connection whose
+    /// handshake finished first, with its handshake time.
+    pub async fn open_fastest(args: &Args) -> Result<(Self, Duration)> {
+        let probes = (0..args.server_urls.len()).map(|server| async move {
+            let start = std::time::Instant::now();
+            Self::open_server(args, server)
+                .await
+                .map(|conn| (conn, start.elapsed()))
+        });
+        let mut fastest: Option<(Self, Duration)> = None;
+        let mut last_error = anyhow::anyhow!("No server URL");
+        for probe in futures_util::future::join_all(probes).await {
+            match probe {
+                Ok((conn, rtt)) if fastest.as_ref().is_none_or(|(_, best)| rtt < *best) => {
+                    fastest = Some((conn, rtt))
+                }
+                Ok(_) => {}
+                Err(e) => last_error = e,
+            }
+        }
+        fastest.ok_or(last_error)
+    }
+
+    /// Index into `--server-url` of the server this is connected to.
+    pub fn server(&self) -> usize {
+        self.server
+    }
+
+    /// Binary encodings are offered as subprotocols, best first, and the
+    /// server picks one; JSON is the fallback.
+    ///
+    /// With `results_url` a second connection is opened there for the
+    /// results, and subscribed to the session by `resume`.
+    async fn open_websocket(url: &str, results_url: Option<&str>, args: &Args) -> Result<Self> {
+        let results = match results_url {
+            Some(results_url) => {
+                let (stream, _) = connect(handshake_request(results_url, args)?, args)
+                    .await
+                    .with_context(|| {
+                        format!("Cannot open a results connection at {}", results_url)
+                    })?;
+                Some(Box::new(stream))
+            }
+            None => None,
+        };
+        let mut offers = Vec::new();
+        if args.codec == Codec::Opus {
+            offers.push(Encoding::Opus {
+                bitrate: args.opus_bitrate,
+            });
+        }
+        match args.wire_format {
+            WireFormat::Msgpack if args.codec == Codec::Pcm => offers.push(Encoding::Msgpack),
+            WireFormat::Protobuf if args.codec == Codec::Pcm => {
+                offers.push(Encoding::Protobuf(args.pcm))
+            }
+            WireFormat::Json if args.codec == Codec::Pcm => {}
+            _ => offers.push(Encoding::Pcm(args.pcm)),
+        }
+        let wanted = offers.first().copied().unwrap_or(Encoding::Json);
+
+        if !offers.is_empty() {
+            let protocols: Vec<&str> = offers.iter().filter_map(|e| e.subprotocol()).collect();
+            let mut request = handshake_request(url, args)?;
+            request.headers_mut().insert(
+                SEC_WEBSOCKET_PROTOCOL,
+                HeaderValue::from_str(&protocols.join(", "))?,
+            );
+            // A server without binary support picks no subprotocol, which
+            // fails the handshake; only that is worth a second, JSON, try
+            match connect(request, args).await {
+                Ok((stream, response)) => {
+                    let chosen = response
+                        .headers()
+                        .get(SEC_WEBSOCKET_PROTOCOL)
+                        .and_then(|v| v.to_str().ok());
+                    if let Some(encoding) = offers.into_iter().find(|e| e.subprotocol() == chosen) {
+                        return Ok(Self::websocket(stream, encoding, wanted, results));
+                    }
+                }
+                Err(e) if refused_subprotocol(&e) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let (stream, _) = connect(handshake_request(url, args)?, args).await?;
+        Ok(Self::websocket(stream, Encoding::Json, wanted, results))
+    }
+
+    fn websocket(
+        stream: WsStream,
+        encoding: Encoding,
+        wanted: Encoding,
+        results: Option<Box<WsStream>>,
+    ) -> Self {
+        let (write, read) = stream.split();
+        let transport = Transport::WebSocket {
+            write,
+            read,
+            results,
+        };
+        Self {
+            encoding,
+            wanted,
+            ..Self::with_transport(transport, Backend::Whisper, 0)
+        }
+    }
+
+    /// A connection over `transport` with JSON encoding and everything
+    /// else at its default; callers override what differs.
+    fn with_transport(transport: Transport, backend: Backend, server: usize) -> Self {
+        Self {
+            transport,
+            encoding: Encoding::Json,
+            wanted: Encoding::Json,
+            server,
+            backend,
+            info: None,
+            send_timeout: None,
+            final_timeout: None,
+            message_ms: None,
+            acks: false,
+            acked: None,
+            mapping: None,
+            notices: Vec::new(),
+        }
+    }
+
+    /// The requested and the negotiated encoding, if the server refused the former.
+    pub fn fallback(&self) -> Option<(Encoding, Encoding)> {
+        (self.encoding != self.wanted).then_some((self.wanted, self.encoding))
+    }
+
+    /// Check the server is still there; an error means the connection is
+    /// dead, e.g. half-open after a network change. gRPC relies on HTTP/2
+    /// keepalive, WebRTC on ICE consent checks and WebTransport on QUIC
+    /// keepalive instead.
+    pub async fn ping(&mut self, timeout: Duration) -> Result<()> {
+        let (write, read, results) = match &mut self.transport {
+            Transport::WebSocket {
+                write,
+                read,
+                results,
+            } => (write, read, results),
+            #[cfg(feature = "grpc")]
+            Transport::Grpc(_) => return Ok(()),
+            #[cfg(feature = "webrtc")]
+            Transport::Webrtc(stream) => return stream.check(),
+            #[cfg(feature = "webtransport")]
+            Transport::Quic(stream) => return stream.check(),
+            #[cfg(feature = "zmq")]
+            Transport::Zmq(_) => return Ok(()),
+            #[cfg(feature = "google")]
+            Transport::Google(_) => return Ok(()),
+            #[cfg(feature = "local")]
+            Transport::Local(_) => return Ok(()),
+        };
+        send_within(write, Message::Ping(Vec::new()), self.send_timeout).await?;
+        pong(read, timeout, &mut self.notices).await?;
+        if let Some(results) = results {
+            send_within(&mut **results, Message::Ping(Vec::new()), self.send_timeout).await?;
+            pong(&mut **results, timeout, &mut self.notices)
+                .await
+                .context("results connection")?;
+        }
+        Ok(())
+    }
+
+    /// Announce `session` to the server. There is no reply; a server that
+    /// doesn't know the session starts it fresh.
+    pub async fn resume(&mut self, session: &Session) -> Result<()> {
+        if self.backend != Backend::Whisper {
+            return Ok(());
+        }
+        let resume = proto::SessionResume {
+            session_id: session.id.to_string(),
+            last_utterance_id: session
+                .last_utterance_id
+                .map(|id| id.to_string())
+                .unwrap_or_default(),
+        };
+        let (write, results) = match &mut self.transport {
+            Transport::WebSocket { write, results, .. } => (write, results),
+            #[cfg(feature = "grpc")]
+            Transport::Grpc(stream) => {
+                return stream
+                    .send(proto::ClientMessage {
+                        message: Some(ClientBody::SessionResume(resume)),
+                    })
+                    .await;
+            }
+            #[cfg(feature = "webrtc")]
+            Transport::Webrtc(stream) => {
+                let message = serde_json::to_string(&ResumeMessage {
+                    msg_type: "resume",
+                    session_id: session.id,
+                    last_utterance_id: session.last_utterance_id,
+                    ack: self.acks,
+                })?;
+                return stream.send(message).await;
+            }
+            #[cfg(feature = "webtransport")]
+            Transport::Quic(stream) => {
+                let message = serde_json::to_vec(&ResumeMessage {
+                    msg_type: "resume",
+                    session_id: session.id,
+                    last_utterance_id: session.last_utterance_id,
+                    ack: self.acks,
+                })?;
+                return stream.send(&message).await;
+            }
+            #[cfg(feature = "zmq")]
+            Transport::Zmq(stream) => {
+                let message = serde_json::to_vec(&ResumeMessage {
+                    msg_type: "resume",
+                    session_id: session.id,
+                    last_utterance_id: session.last_utterance_id,
+                    ack: self.acks,
+                })?;
+                return stream.send(message).await;
+            }
+            #[cfg(feature = "google")]
+            Transport::Google(_) => return Ok(()),
+            #[cfg(feature = "local")]
+            Transport::Local(_) => return Ok(()),
+        };
+        let message = match self.encoding {
+            Encoding::Protobuf(_) => Message::Binary(
+                proto::ClientMessage {
+                    message: Some(ClientBody::SessionResume(resume)),
+                }
+                .encode_to_vec(),
+            ),
+            // Servers read text frames as JSON whatever the subprotocol
+            _ => Message::Text(serde_json::to_string(&ResumeMessage {
+                msg_type: "resume",
+                session_id: session.id,
+                last_utterance_id: session.last_utterance_id,
+                ack: self.acks,
+            })?),
+        };
+        send_within(write, message, self.send_timeout).await?;
+        if let Some(results) = results {
+            let subscribe = serde_json::to_string(&SubscribeMessage {
+                msg_type: "subscribe",
+                session_id: session.id,
+            })?;
+            send_within(&mut **results, Message::Text(subscribe), self.send_timeout).await?;
+        }
+        Ok(())
+    }
+
+    /// Send the session settings (--language, --model, --hotword and the
+    /// sample rate) with the client metadata and wait briefly for the
+    /// server's acknowledgment. Only the whisper server takes them, over
+    /// WebSocket or, without the metadata or an acknowledgment, gRPC;
+    /// elsewhere this does nothing.
+    pub async fn configure(&mut self, args: &Args, device: Option<&str>) -> Result<()> {
+        if self.backend != Backend::Whisper {
+            return Ok(());
+        }
+        let (write, read) = match &mut self.transport {
+            Transport::WebSocket { write, read, .. } => (write, read),
+            // No reply: gRPC servers take it along with the first audio
+            #[cfg(feature = "grpc")]
+            Transport::Grpc(stream) => {
+                let config = proto::SessionConfig {
+                    sample_rate: args.sample_rate,
+                    language: args.language.clone().unwrap_or_default(),
+                    model: args.model.clone().unwrap_or_default(),
+                    hotwords: args.hotwords.clone(),
+                    word_timestamps: args.word_timestamps,
+                };
+                return stream
+                    .send(proto::ClientMessage {
+                        message: Some(ClientBody::SessionConfig(config)),
+                    })
+                    .await;
+            }
+            // The other transports, when built in
+            #[allow(unreachable_patterns)]
+            _ => return Ok(()),
+        };
+        let config = ConfigMessage {
+            msg_type: "config",
+            sample_rate: args.sample_rate,
+            language: args.language.as_deref(),
+            model: args.model.as_deref(),
+            hotwords: &args.hotwords,
+            word_timestamps: args.word_timestamps,
+            client: ClientMetadata {
+                name: env!("CARGO_PKG_NAME"),
+                version: env!("CARGO_PKG_VERSION"),
+                os: std::env::consts::OS,
+                arch: std::env::consts::ARCH,
+                device,
+                encoding: self.encoding.to_string(),
+                params: serde_json::json!({
+                    "chunk_ms": args.chunk_ms,
+                    "onset_ms": args.onset_ms,
+                    "silence_threshold_ms": args.silence_threshold_ms,
+                    "adaptive_silence": args.adaptive_silence,
+                    "post_roll_ms": args.post_roll_ms,
+                    "min_energy": args.min_energy,
+                    "vad_mode": args.vad_mode.map(|m| format!("{:?}", m).to_lowercase()),
+                    "agc": args.agc,
+                    "denoise": args.denoise,
+                }),
+            },
+        };
+        let config = Message::Text(serde_json::to_string(&config)?);
+        send_within(write, config, self.send_timeout).await?;
+        let notices = &mut self.notices;
+        let acked = &mut self.acked;
+        let ack = tokio::time::timeout(CONFIG_ACK_TIMEOUT, async {
+            loop {
+                match read.next().await {
+                    Some(Ok(Message::Text(text))) => {
+                        if let Some(offset) = ack_offset(&text) {
+                            *acked = Some(offset);
+                            continue;
+                        }
+                        if let Ok(info) = serde_json::from_str::<ServerInfo>(&text) {
+                            if info.msg_type == "config_ack" {
+                                return Ok(info);
+                            }
+                        }
+                        if let Ok(reply) = serde_json::from_str::<ServerResponse>(&text) {
+                            if reply.is_notice() {
+                                notices.push(reply);
+                            }
+                        }
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => return Err(e.into()),
+                    None => anyhow::bail!("connection closed"),
+                }
+            }
+        })
+        .await;
+        match ack {
+            Ok(info) => self.info = Some(info?),
+            Err(_) => self.info = None,
+        }
+        Ok(())
+    }
+
+    /// The latest offset the server acknowledged since the last call.
+    pub fn take_ack(&mut self) -> Option<u64> {
+        self.acked.take()
+    }
+
+    /// Status, warning and error messages received since the last call.
+    pub fn take_notices(&mut self) -> Vec<ServerResponse> {
+        std::mem::take(&mut self.notices)
+    }
+
+    /// The server's reply to `configure`, if it sent one.
+    pub fn info(&self) -> Option<&ServerInfo> {
+        self.info.as_ref()
+    }
+
+    /// Send audio for transcription and wait for the reply. An error means
+    /// the connection is gone; a missing or unparseable reply gives `None`.
+    pub async fn transcribe(
+        &mut self,
+        audio: &[f32],
+        sample_rate: u32,
+        utterance_id: Uuid,
+    ) -> Result<Option<ServerResponse>> {
+        self.request(audio, sample_rate, utterance_id, false).await
+    }
+
+    /// `transcribe` for the audio so far of an utterance still in progress
+    /// (--partial-interval-ms). The server neither counts it toward
+    /// acknowledgments nor keeps its reply for a repeat.
+    pub async fn transcribe_partial(
+        &mut self,
+        audio: &[f32],
+        sample_rate: u32,
+        utterance_id: Uuid,
+    ) -> Result<Option<ServerResponse>> {
+        self.request(audio, sample_rate, utterance_id, true).await
+    }
+
+    async fn request(
+        &mut self,
+        audio: &[f32],
+        sample_rate: u32,
+        utterance_id: Uuid,
+        partial: bool,
+    ) -> Result<Option<ServerResponse>> {
+        let (write, read, results) = match &mut self.transport {
+            Transport::WebSocket {
+                write,
+                read,
+                results,
+            } => (write, read, results),
+            #[cfg(feature = "google")]
+            Transport::Google(stt) => return stt.transcribe(audio, sample_rate).await,
+            #[cfg(feature = "local")]
+            Transport::Local(whisper) => return Ok(whisper.transcribe(audio, sample_rate)),
+            #[cfg(feature = "webrtc")]
+            Transport::Webrtc(stream) => {
+                let bitrate = match self.encoding {
+                    Encoding::Opus { bitrate } => bitrate,
+                    _ => 0,
+                };
+                return stream
+                    .transcribe(
+                        audio,
+                        sample_rate,
+                        utterance_id,
+                        bitrate,
+                        self.final_timeout,
+                        &mut self.notices,
+                    )
+                    .await;
+            }
+            #[cfg(feature = "webtransport")]
+            Transport::Quic(stream) => {
+                let mut reply = None;
+                for message in
+                    utterance_messages(self.encoding, audio, sample_rate, utterance_id, partial)?
+                {
+                    reply = stream.request(&message.into_data()).await?;
+                }
+                return Ok(reply);
+            }
+            #[cfg(feature = "zmq")]
+            Transport::Zmq(stream) => {
+                let mut reply = None;
+                for message in
+                    utterance_messages(self.encoding, audio, sample_rate, utterance_id, partial)?
+                {
+                    reply = stream.request(message.into_data()).await?;
+                }
+                return Ok(reply);
+            }
+            #[cfg(feature = "grpc")]
+            Transport::Grpc(stream) => {
+                let format = match self.encoding {
+                    Encoding::Protobuf(format) => format,
+                    _ => SampleFormat::F32,
+                };
+                let [frame, end] =
+                    protobuf_utterance(audio, sample_rate, utterance_id, format, partial);
+                stream.send(frame).await?;
+                stream.send(end.clone()).await?;
+                let mut retried = false;
+                let mut deadline = deadline(self.final_timeout);
+                loop {
+                    let Some(next) = until(deadline, stream.next()).await else {
+                        let timeout = self.final_timeout.unwrap_or_default();
+                        self.notices.push(ServerResponse::unanswered(
+                            utterance_id,
+                            timeout,
+                            retried,
+                        ));
+                        if retried {
+                            return Ok(None);
+                        }
+                        retried = true;
+                        deadline = self::deadline(self.final_timeout);
+                        stream.send(end.clone()).await?;
+                        continue;
+                    };
+                    let Some(message) = next? else {
+                        anyhow::bail!("gRPC stream ended");
+                    };
+                    if let Some(reply) = protobuf_reply(message) {
+                        if reply.as_ref().is_some_and(|r| r.is_stale(utterance_id)) {
+                            continue;
+                        }
+                        if let Some(reply) = screen(&mut self.notices, reply) {
+                            return Ok(reply);
+                        }
+                    }
+                }
+            }
+        };
+        match self.backend {
+            Backend::Openai => {
+                return crate::openai::transcribe(
+                    write,
+                    read,
+                    audio,
+                    sample_rate,
+                    self.message_ms,
+                    self.send_timeout,
+                )
+                .await
+            }
+            Backend::Azure => {
+                return crate::azure::transcribe(
+                    write,
+                    read,
+                    audio,
+                    sample_rate,
+                    self.message_ms,
+                    self.send_timeout,
+                )
+                .await
+            }
+            Backend::Vosk => {
+                return crate::vosk::transcribe(
+                    write,
+                    read,
+                    audio,
+                    sample_rate,
+                    self.message_ms,
+                    self.send_timeout,
+                )
+                .await
+            }
+            Backend::Whisper | Backend::Google | Backend::Local => {}
+        }
+        let read: &mut Replies = match results {
+            Some(results) => results,
+            None => read,
+        };
+        let messages =
+            utterance_messages(self.encoding, audio, sample_rate, utterance_id, partial)?;
+        for message in messages.iter().cloned() {
+            send_within(write, message, self.send_timeout).await?;
+        }
+        let mut retried = false;
+        let mut deadline = deadline(self.final_timeout);
+        loop {
+            let Some(next) = until(deadline, read.next()).await else {
+                let timeout = self.final_timeout.unwrap_or_default();
+                self.notices
+                    .push(ServerResponse::unanswered(utterance_id, timeout, retried));
+                if retried {
+                    return Ok(None);
+                }
+                // The server answers a repeated utterance from its cache
+                retried = true;
+                deadline = self::deadline(self.final_timeout);
+                for message in messages.iter().cloned() {
+                    send_within(write, message, self.send_timeout).await?;
+                }
+                continue;
+            };
+            let reply = match next {
+                // Control frames, e.g. a late pong from `ping`
+                Some(Ok(Message::Ping(_) | Message::Pong(_))) => continue,
+                Some(Ok(Message::Text(text))) => {
+                    trace!("[server] {}", text);
+                    if let Some(offset) = ack_offset(&text) {
+                        self.acked = Some(offset);
+                        continue;
+                    }
+                    match &self.mapping {
+                        Some(mapping) => crate::mapping::map_reply(mapping, &text),
+                        None => serde_json::from_str(&text).ok(),
+                    }
+                }
+                Some(Ok(Message::Binary(data))) => match self.encoding {
+                    Encoding::Msgpack => rmp_serde::from_slice(&data).ok(),
+                    Encoding::Protobuf(_) => match proto::ServerMessage::decode(data.as_slice()) {
+                        Ok(message) => match protobuf_reply(message) {
+                            Some(reply) => reply,
+                            None => continue,
+                        },
+                        Err(_) => None,
+                    },
+                    _ => None,
+                },
+                _ => None,
+            };
+            // Partials from servers that stream them, and late replies
+            if reply
+                .as_ref()
+                .is_some_and(|r| r.msg_type == "partial" || r.is_stale(utterance_id))
+            {
+                continue;
+            }
+            if let Some(reply) = screen(&mut self.notices, reply) {
+                return Ok(reply);
+            }
+        }
+    }
+}
+
+/// The encoding for transports that carry each utterance as one message
+/// they frame themselves: --codec opus, or --wire-format json or binary
+/// (MessagePack and protobuf fall back to binary).
+#[cfg(any(feature = "webtransport", feature = "zmq"))]
+fn single_message_encoding(args: &Args) -> Encoding {
+    match (args.codec, args.wire_format) {
+        (Codec::Opus, _) => Encoding::Opus {
+            bitrate: args.opus_bitrate,
+        },
+        (Codec::Pcm, WireFormat::Json) => Encoding::Json,
+        (Codec::Pcm, _) => Encoding::Pcm(args.pcm),
+    }
+}
+
+/// The messages carrying one utterance in `encoding`.
+fn utterance_messages(
+    encoding: Encoding,
+    audio: &[f32],
+    sample_rate: u32,
+    utterance_id: Uuid,
+    partial: bool,
+) -> Result<Vec<Message>> {
+    Ok(match encoding {
+        Encoding::Json => vec![Message::Text(json_message(
+            audio,
+            sample_rate,
+            utterance_id,
+            partial,
+        ))],
+        Encoding::Msgpack => vec![Message::Binary(msgpack_message(
+            audio,
+            sample_rate,
+            utterance_id,
+            partial,
+        )?)],
+        Encoding::Pcm(format) => vec![Message::Binary(binary_frame(
+            audio,
+            sample_rate,
+            utterance_id,
+            format,
+            partial,
+        ))],
+        Encoding::Opus { bitrate } => {
+            let mut frame = binary_header(OPUS_FORMAT, sample_rate, utterance_id, partial);
+            frame.extend(opus_packets(audio, sample_rate, bitrate)?);
+            vec![Message::Binary(frame)]
+        }
+        Encoding::Protobuf(format) => {
+            protobuf_utterance(audio, sample_rate, utterance_id, format, partial)
+                .iter()
+                .map(|m| Message::Binary(m.encode_to_vec()))
+                .collect()
+        }
+    })
+}
+
+/// Samples at `sample_rate` per message to a backend that streams audio:
+/// `message_ms` worth, or the backend's `default`.
+pub fn message_samples(message_ms: Option<u32>, sample_rate: u32, default: usize) -> usize {
+    match message_ms {
+        Some(ms) => (sample_rate as u64 * ms as u64 / 1000).max(1) as usize,
+        None => default,
+    }
+}
+
+/// When a wait of `timeout` that starts now runs out; `None` never does.
+pub fn deadline(timeout: Option<Duration>) -> Option<Instant> {
+    timeout.map(|timeout| Instant::now() + timeout)
+}
+
+/// `next` before `deadline`, or `None` once it passes. Waits for a reply
+/// share one deadline, so messages that aren't the reply don't extend it.
+pub async fn until<T>(deadline: Option<Instant>, next: impl Future<Output = T>) -> Option<T> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, next).await.ok(),
+        None => Some(next.await),
+    }
+}
+
+/// Send on a WebSocket, failing after `timeout` so that a stalled TCP
+/// connection is dropped instead of blocking the caller; `None` waits.
+pub async fn send_within<S>(
+    write: &mut S,
+    message: Message,
+    timeout: Option<Duration>,
+) -> Result<()>
+where
+    S: Sink<Message, Error = tungstenite::Error> + Unpin,
+{
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, write.send(message))
+            .await
+            .context("send timed out")??,
+        None => write.send(message).await?,
+    }
+    Ok(())
+}
+
+/// Wait up to `timeout` for a pong on `read`, setting aside notices.
+async fn pong<S>(read: &mut S, timeout: Duration, notices: &mut Vec<ServerResponse>) -> Result<()>
+where
+    S: Stream<Item = tungstenite::Result<Message>> + Unpin,
+{
+    tokio::time::timeout(timeout, async {
+        loop {
+            match read.next().await {
+                Some(Ok(Message::Pong(_))) => return Ok(()),
+                Some(Ok(Message::Text(text))) => {
+                    if let Ok(reply) = serde_json::from_str::<ServerResponse>(&text) {
+                        if reply.is_notice() {
+                            notices.push(reply);
+                        }
+                    }
+                }
+                // Not waiting on a reply, so anything else is stale
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+                None => anyhow::bail!("connection closed"),
+            }
+        }
+    })
+    .await
+    .context("no pong")?
+}
+
+/// --ping-interval-ms and --ping-timeout-ms, or `None` if keepalive is off.
+pub fn ping_settings(args: &Args) -> Option<(Duration, Duration)> {
+    (args.ping_interval_ms > 0).then(|| {
+        (
+            Duration::from_millis(args.ping_interval_ms),
+            Duration::from_millis(args.ping_timeout_ms),
+        )
+    })
+}
+
+/// Parses a --header value of the form `Name: value`.
+pub fn parse_header(s: &str) -> Result<(String, String), String> {
+    let (name, value) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected `Name: value`, got `{}`", s))?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes())
+        .map_err(|_| format!("`{}` is not a valid header name", name.trim()))?;
+    let value = value.trim();
+    HeaderValue::from_str(value).map_err(|_| format!("invalid value for header `{}`", name))?;
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// `whisper_client/<version> (<os>; <arch>)`, so server logs show which
+/// client build connected.
+pub fn user_agent() -> String {
+    format!(
+        "{}/{} ({}; {})",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    )
+}
+
+/// Headers for the handshake: --header values, a User-Agent, then credentials (--token as
+/// a bearer token, --api-key as `X-API-Key`) unless --auth-query puts them in
+/// the URL.
+pub fn handshake_headers(args: &Args) -> Vec<(String, String)> {
+    let mut headers = args.headers.clone();
+    if !headers.iter().any(|(name, _)| name == "user-agent") {
+        headers.push(("user-agent".into(), user_agent()));
+    }
+    if args.auth_query.is_none() {
+        if let Some(token) = &args.token {
+            headers.push(("authorization".into(), format!("Bearer {}", token)));
+        }
+        if let Some(key) = &args.api_key {
+            headers.push(("x-api-key".into(), key.clone()));
+        }
+    }
+    headers
+}
+
+pub fn handshake_request(url: &str, args: &Args) -> Result<Request> {
+    // A unix:// URL is a socket path followed by the HTTP path; the request
+    // itself goes to a nominal ws://localhost
+    let mut socket = None;
+    let url = match url.strip_prefix("unix://") {
+        Some(rest) => {
+            let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+            let (path, http_path) = crate::socket::split_unix_path(path);
+            socket = Some(crate::socket::UnixPath(path));
+            let query = if query.is_empty() {
+                String::new()
+            } else {
+                format!("?{}", query)
+            };
+            format!("ws://localhost{}{}", http_path, query)
+        }
+        None => url.to_string(),
+    };
+    let url = url.as_str();
+    let credential = args.token.as_ref().or(args.api_key.as_ref());
+    let mut request = match (&args.auth_query, credential) {
+        (Some(name), Some(credential)) => {
+            let mut url = url::Url::parse(url)?;
+            url.query_pairs_mut().append_pair(name, credential);
+            url.as_str().into_client_request()?
+        }
+        _ => url.into_client_request()?,
+    };
+    for (name, value) in handshake_headers(args) {
+        request.headers_mut().append(
+            HeaderName::from_bytes(name.as_bytes())?,
+            HeaderValue::from_str(&value)?,
+        );
+    }
+    if args.deflate {
+        request.headers_mut().insert(
+            SEC_WEBSOCKET_EXTENSIONS,
+            HeaderValue::from_static(deflate::OFFER),
+        );
+    }
+    if let Some(socket) = socket {
+        request.extensions_mut().insert(socket);
+    }
+    Ok(request)
+}
+
+/// The handshake runs over `Deflate`, which compresses frames if the
+/// request offers permessage-deflate and the server takes it up. TLS is set
+/// up here rather than by tokio-tungstenite so that it can go underneath.
+pub async fn connect(request: Request, args: &Args) -> Result<(WsStream, Response)> {
+    let uri = request.uri();
+    let scheme = uri.scheme_str().unwrap_or("ws");
+    let host = uri
+        .host()
+        .context("Server URL has no host")?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let stream = if let Some(UnixPath(path)) = request.extensions().get::<UnixPath>().cloned() {
+        Socket::unix(&path)
+            .await
+            .with_context(|| format!("Cannot connect to {}", path.display()))?
+    } else {
+        let port = uri
+            .port_u16()
+            .unwrap_or(if scheme == "wss" { 443 } else { 80 });
+        let stream = match crate::proxy::for_target(scheme, &host, args.proxy.as_deref())? {
+            Some(proxy) => crate::proxy::tunnel(&proxy, &host, port).await?,
+            None => TcpStream::connect((host.as_str(), port)).await?,
+        };
+        Socket::Tcp(stream)
+    };
+    let stream = if scheme == "wss" {
+        let connector = match args.tls.clone() {
+            Some(connector) => connector,
+            None => native_tls::TlsConnector::new()?,
+        };
+        let stream = tokio_native_tls::TlsConnector::from(connector)
+            .connect(&host, stream)
+            .await?;
+        MaybeTlsStream::NativeTls(stream)
+    } else {
+        MaybeTlsStream::Plain(stream)
+    };
+    let offered = request.headers().contains_key(SEC_WEBSOCKET_EXTENSIONS);
+    Ok(client_async_with_config(request, Deflate::new(stream, offered), None).await?)
+}
+
+/// Whether a handshake failed only because the server took none of the
+/// offered subprotocols, as opposed to not being reachable at all.
+fn refused_subprotocol(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<tungstenite::Error>(),
+        Some(tungstenite::Error::Protocol(
+            tungstenite::error::ProtocolError::SecWebSocketSubProtocolError(_)
+        ))
+    )
+}
+
+/// TLS settings for wss:// from --ca-cert, --client-cert/--client-key and
+/// --insecure; `None` keeps the system defaults.
+pub fn tls_connector(args: &Args) -> Result<Option<native_tls::TlsConnector>> {
+    if args.ca_cert.is_none() && args.client_cert.is_none() && !args.insecure {
+        return Ok(None);
+    }
+    let read = |path: &std::path::Path| {
+        std::fs::read(path).with_context(|| format!("Cannot read {}", path.display()))
+    };
+    let mut builder = native_tls::TlsConnector::builder();
+    if let Some(path) = &args.ca_cert {
+        let cert = native_tls::Certificate::from_pem(&read(path)?)
+            .with_context(|| format!("Invalid CA certificate {}", path.display()))?;
+        builder.add_root_certificate(cert);
+    }
+    if let (Some(cert), Some(key)) = (&args.client_cert, &args.client_key) {
+        let identity = native_tls::Identity::from_pkcs8(&read(cert)?, &read(key)?)
+            .context("Invalid client certificate or key")?;
+        builder.identity(identity);
+    }
+    if args.insecure {
+        builder
+            .danger_accept_invalid_certs(true)
+            .danger_accept_invalid_hostnames(true);
+    }
+    Ok(Some(builder.build()?))
+}
+
+fn json_message(audio: &[f32], sample_rate: u32, utterance_id: Uuid, partial: bool) -> String {
+    let bytes: Vec<u8> = audio.iter().flat_map(|&s| s.to_le_bytes()).collect();
+    let b64 = base64::engine::general_purpose::STANDARD.encode(&bytes);
+    serde_json::to_string(&TranscribeMessage {
+        msg_type: "transcribe",
+        audio: b64,
+        sample_rate,
+        utterance_id,
+        partial,
+    })
+    .unwrap()
+}
+
+#[derive(Serialize)]
+struct MsgpackTranscribe<'a> {
+    #[serde(rename = "type")]
+    msg_type: &'static str,
+    /// float32 little-endian samples
+    #[serde(with = "serde_bytes")]
+    audio: &'a [u8],
+    sample_rate: u32,
+    utterance_id: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    partial: bool,
+}
+
+/// Same fields as the JSON message, with the samples as a bin value.
+fn msgpack_message(
+    audio: &[f32],
+    sample_rate: u32,
+    utterance_id: Uuid,
+    partial: bool,
+) -> Result<Vec<u8>> {
+    let bytes: Vec<u8> = audio.iter().flat_map(|&s| s.to_le_bytes()).collect();
+    Ok(rmp_serde::to_vec_named(&MsgpackTranscribe {
+        msg_type: "transcribe",
+        audio: &bytes,
+        sample_rate,
+        utterance_id: utterance_id.to_string(),
+        partial,
+    })?)
+}
+
+/// `WPCM`, sample format (0 = f32, 1 = i16, 2 = Opus), flags (bit 0 for a
+/// partial), 2 reserved bytes, sample rate (u32) and the 16-byte utterance
+/// ID; all little-endian.
+fn binary_header(format: u8, sample_rate: u32, utterance_id: Uuid, partial: bool) -> Vec<u8> {
+    let mut header = Vec::with_capacity(28);
+    header.extend_from_slice(BINARY_MAGIC);
+    header.extend_from_slice(&[format, partial as u8, 0, 0]);
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    header.extend_from_slice(utterance_id.as_bytes());
+    header
+}
+
+const OPUS_FORMAT: u8 = 2;
+
+/// The header followed by little-endian samples.
+fn binary_frame(
+    audio: &[f32],
+    sample_rate: u32,
+    utterance_id: Uuid,
+    format: SampleFormat,
+    partial: bool,
+) -> Vec<u8> {
+    let code = match format {
+        SampleFormat::F32 => 0,
+        SampleFormat::I16 => 1,
+    };
+    let mut frame = binary_header(code, sample_rate, utterance_id, partial);
+    frame.extend(encode_samples(audio, format));
+    frame
+}
+
+fn encode_samples(audio: &[f32], format: SampleFormat) -> Vec<u8> {
+    match format {
+        SampleFormat::F32 => audio.iter().flat_map(|&s| s.to_le_bytes()).collect(),
+        SampleFormat::I16 => audio
+            .iter()
+            .flat_map(|&s| ((s.clamp(-1.0, 1.0) * 32767.0) as i16).to_le_bytes())
+            .collect(),
+    }
+}
+
+/// The whole utterance as one audio frame, then its end.
+fn protobuf_utterance(
+    audio: &[f32],
+    sample_rate: u32,
+    utterance_id: Uuid,
+    format: SampleFormat,
+    partial: bool,
+) -> [proto::ClientMessage; 2] {
+    let frame = proto::AudioFrame {
+        utterance_id: utterance_id.to_string(),
+        sample_rate,
+        format: match format {
+            SampleFormat::F32 => proto::SampleFormat::F32,
+            SampleFormat::I16 => proto::SampleFormat::I16,
+        } as i32,
+        samples: encode_samples(audio, format),
+        partial,
+    };
+    let end = proto::VadEnd {
+        utterance_id: utterance_id.to_string(),
+    };
+    [ClientBody::AudioFrame(frame), ClientBody::VadEnd(end)].map(|message| proto::ClientMessage {
+        message: Some(message),
+    })
+}
+
+/// The reply a server message gives, or `None` for interim transcripts,
+/// which don't end the exchange.
+fn protobuf_reply(message: proto::ServerMessage) -> Option<Option<ServerResponse>> {
+    match message.message {
+        Some(ServerBody::Partial(_)) => None,
+        Some(ServerBody::FinalResult(result)) => Some(Some(ServerResponse {
+            msg_type: "result".to_string(),
+            text: Some(result.text),
+            sample: None,
+            message: None,
+            utterance_id: Some(result.utterance_id),
+            words: result
+                .words
+                .into_iter()
+                .map(|w| Word {
+                    word: w.word,
+                    start: w.start.into(),
+                    end: w.end.into(),
+                    probability: w.probability.map(f64::from),
+                })
+                .collect(),
+            confidence: result.confidence.map(f64::from),
+            speaker: (!result.speaker.is_empty()).then_some(result.speaker),
+        })),
+        Some(ServerBody::Noise(noise)) => Some(Some(ServerResponse {
+            msg_type: "noise".to_string(),
+            text: None,
+            sample: Some(noise.sample),
+            message: None,
+            utterance_id: Some(noise.utterance_id),
+            words: Vec::new(),
+            confidence: None,
+            speaker: None,
+        })),
+        Some(ServerBody::Error(error)) => Some(Some(ServerResponse {
+            msg_type: "error".to_string(),
+            text: None,
+            sample: None,
+            message: Some(error.message),
+            utterance_id: Some(error.utterance_id),
+            words: Vec::new(),
+            confidence: None,
+            speaker: None,
+        })),
+        None => Some(None),
+    }
+}
+
+/// Sample rates libopus encodes at
+pub const OPUS_SAMPLE_RATES: [u32; 5] = [8000, 12000, 16000, 24000, 48000];
+
+/// Encode as 20ms Opus packets. A fresh encoder per utterance lets the
+/// server decode each one on its own; the last packet is padded with silence.
+#[cfg(feature = "opus")]
+pub fn opus_frames(audio: &[f32], sample_rate: u32, bitrate: u32) -> Result<Vec<Vec<u8>>> {
+    let mut encoder =
+        opus::Encoder::new(sample_rate, opus::Channels::Mono, opus::Application::Voip)?;
+    encoder.set_bitrate(opus::Bitrate::Bits(bitrate as i32))?;
+    let frame_len = sample_rate as usize / 50;
+    // Largest packet Opus can produce
+    let mut packet = [0u8; 1275];
+    let mut packets = Vec::new();
+    for chunk in audio.chunks(frame_len) {
+        let mut input = chunk.to_vec();
+        input.resize(frame_len, 0.0);
+        let len = encoder.encode_float(&input, &mut packet)?;
+        packets.push(packet[..len].to_vec());
+    }
+    Ok(packets)
+}
+
+/// The packets of `opus_frames`, each prefixed with its length (u16 LE).
+#[cfg(feature = "opus")]
+fn opus_packets(audio: &[f32], sample_rate: u32, bitrate: u32) -> Result<Vec<u8>> {
+    let mut payload = Vec::new();
+    for packet in opus_frames(audio, sample_rate, bitrate)? {
+        payload.extend_from_slice(&(packet.len() as u16).to_le_bytes());
+        payload.extend_from_slice(&packet);
+    }
+    Ok(payload)
+}
+
+#[cfg(not(feature = "opus"))]
+fn opus_packets(_audio: &[f32], _sample_rate: u32, _bitrate: u32) -> Result<Vec<u8>> {
+    anyhow::bail!("Built without Opus support")
+}
@@ -0,0 +1,566 @@
+//! permessage-deflate (RFC 7692) for --deflate. tungstenite has no
+//! extension support and fails on frames with RSV1 set, so this sits between
+//! it and the socket: it reads the server's answer to the offer from the
+//! handshake response as it passes through, then compresses outgoing data
+//! frames and inflates incoming ones, so tungstenite only ever sees plain
+//! frames. A server that declines leaves a pass-through.
+
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// The `Sec-WebSocket-Extensions` offer. Window sizes aren't offered: the
+/// default 15 bits inflate whatever the server picks.
+pub const OFFER: &str = "permessage-deflate";
+
+/// Ends every compressed message, and is left off on the wire
+const TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+const RSV1: u8 = 0x40;
+/// Encoded bytes held before writes wait for the socket
+const WRITE_BUFFER: usize = 64 * 1024;
+
+enum State {
+    /// Before the end of the response head
+    Handshake,
+    Plain,
+    Deflate(Params),
+}
+
+#[derive(Clone, Copy)]
+struct Params {
+    /// The server starts each message with an empty window
+    server_no_context_takeover: bool,
+    /// And asks the client to do the same
+    client_no_context_takeover: bool,
+}
+
+pub struct Deflate<S> {
+    inner: S,
+    state: State,
+    /// Read from the socket, not yet a whole frame
+    raw: Vec<u8>,
+    /// Ready for tungstenite, from `decoded_pos`
+    decoded: Vec<u8>,
+    decoded_pos: usize,
+    /// The incoming message in progress is compressed
+    inflating: bool,
+    inflate: Decompress,
+    /// Written by tungstenite, not yet a whole frame
+    pending: Vec<u8>,
+    /// Ready for the socket, from `out_pos`
+    out: Vec<u8>,
+    out_pos: usize,
+    deflate: Compress,
+}
+
+impl<S> Deflate<S> {
+    /// Wrap `inner`; `offered` says whether the handshake request offers
+    /// the extension, otherwise the stream passes through untouched.
+    pub fn new(inner: S, offered: bool) -> Self {
+        Self {
+            inner,
+            state: if offered {
+                State::Handshake
+            } else {
+                State::Plain
+            },
+            raw: Vec::new(),
+            decoded: Vec::new(),
+            decoded_pos: 0,
+            inflating: false,
+            inflate: Decompress::new(false),
+            pending: Vec::new(),
+            out: Vec::new(),
+            out_pos: 0,
+            deflate: Compress::new(Compression::default(), false),
+        }
+    }
+
+    /// Move what can be decoded from `raw` to `decoded`; false if it needs
+    /// more input.
+    fn decode(&mut self) -> io::Result<bool> {
+        match self.state {
+            State::Handshake => {
+                let Some(end) = self.raw.windows(4).position(|w| w == b"\r\n\r\n") else {
+                    return Ok(false);
+                };
+                let head: Vec<u8> = self.raw.drain(..end + 4).collect();
+                self.state = match negotiated(&String::from_utf8_lossy(&head)) {
+                    Some(params) => State::Deflate(params),
+                    None => State::Plain,
+                };
+                self.decoded.extend(head);
+                Ok(true)
+            }
+            State::Plain => {
+                if self.raw.is_empty() {
+                    return Ok(false);
+                }
+                self.decoded.append(&mut self.raw);
+                Ok(true)
+            }
+            State::Deflate(params) => {
+                let Some(frame) = Frame::parse(&self.raw) else {
+                    return Ok(false);
+                };
+                let bytes: Vec<u8> = self.raw.drain(..frame.len).collect();
+                match frame.opcode() {
+                    1 | 2 => self.inflating = frame.head[0] & RSV1 != 0,
+                    0 => {}
+                    // Control frames are never compressed
+                    _ => {
+                        self.decoded.extend(bytes);
+                        return Ok(true);
+                    }
+                }
+                if !self.inflating {
+                    self.decoded.extend(bytes);
+                    return Ok(true);
+                }
+                let mut payload = frame.payload(&bytes);
+                if frame.fin() {
+                    payload.extend_from_slice(&TAIL);
+                }
+                let plain = inflate(&mut self.inflate, &payload)?;
+                if frame.fin() {
+                    self.inflating = false;
+                    if params.server_no_context_takeover {
+                        self.inflate.reset(false);
+                    }
+                }
+                self.decoded
+                    .extend(frame_head(frame.head[0] & !RSV1, plain.len(), None));
+                self.decoded.extend(plain);
+                Ok(true)
+            }
+        }
+    }
+
+    /// Move what can be encoded from `pending` to `out`.
+    fn encode(&mut self) -> io::Result<()> {
+        let State::Deflate(params) = self.state else {
+            // Still the handshake request, or nothing to compress
+            self.out.append(&mut self.pending);
+            return Ok(());
+        };
+        while let Some(frame) = Frame::parse(&self.pending) {
+            let bytes: Vec<u8> = self.pending.drain(..frame.len).collect();
+            // tungstenite sends each message as one frame; anything else
+            // goes as is, uncompressed
+            if !matches!(frame.opcode(), 1 | 2) || !frame.fin() {
+                self.out.extend(bytes);
+                continue;
+            }
+            let mut compressed = deflate(&mut self.deflate, &frame.payload(&bytes))?;
+            if params.client_no_context_takeover {
+                self.deflate.reset();
+            }
+            self.out.extend(frame_head(
+                frame.head[0] | RSV1,
+                compressed.len(),
+                frame.mask,
+            ));
+            if let Some(mask) = frame.mask {
+                apply_mask(&mut compressed, mask);
+            }
+            self.out.extend(compressed);
+        }
+        Ok(())
+    }
+}
+
+impl<S: AsyncWrite + Unpin> Deflate<S> {
+    /// Write out `out`, Pending until it's all gone.
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.out_pos < self.out.len() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.out[self.out_pos..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.out_pos += n;
+        }
+        self.out.clear();
+        self.out_pos = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Deflate<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.decoded_pos < this.decoded.len() {
+                let n = buf.remaining().min(this.decoded.len() - this.decoded_pos);
+                buf.put_slice(&this.decoded[this.decoded_pos..this.decoded_pos + n]);
+                this.decoded_pos += n;
+                if this.decoded_pos == this.decoded.len() {
+                    this.decoded.clear();
+                    this.decoded_pos = 0;
+                }
+                return Poll::Ready(Ok(()));
+            }
+            if this.decode()? {
+                continue;
+            }
+            let mut chunk = [0u8; 8192];
+            let mut read = ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut read))?;
+            if read.filled().is_empty() {
+                // End of stream; a partial frame left in `raw` is lost with it
+                return Poll::Ready(Ok(()));
+            }
+            this.raw.extend_from_slice(read.filled());
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Deflate<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.out.len() - this.out_pos >= WRITE_BUFFER {
+            ready!(this.poll_drain(cx))?;
+        }
+        this.pending.extend_from_slice(buf);
+        this.encode()?;
+        // Written out here if the socket takes it, else on flush
+        if let Poll::Ready(Err(e)) = this.poll_drain(cx) {
+            return Poll::Ready(Err(e));
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+/// The extension's parameters if the response head accepted it.
+fn negotiated(head: &str) -> Option<Params> {
+    let value = head.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("sec-websocket-extensions")
+            .then_some(value)
+    })?;
+    let mut params = value.split(';').map(str::trim);
+    if params.next() != Some(OFFER) {
+        return None;
+    }
+    let mut negotiated = Params {
+        server_no_context_takeover: false,
+        client_no_context_takeover: false,
+    };
+    for param in params {
+        match param.split('=').next().map(str::trim) {
+            Some("server_no_context_takeover") => negotiated.server_no_context_takeover = true,
+            Some("client_no_context_takeover") => negotiated.client_no_context_takeover = true,
+            _ => {}
+        }
+    }
+    Some(negotiated)
+}
+
+/// Where a whole frame at the start of a buffer is.
+struct Frame {
+    /// First two header bytes
+    head: [u8; 2],
+    mask: Option<[u8; 4]>,
+    /// Header length
+    offset: usize,
+    /// Header and payload length
+    len: usize,
+}
+
+impl Frame {
+    /// The frame at the start of `data`, once all of it is there.
+    fn parse(data: &[u8]) -> Option<Self> {
+        let head = [*data.first()?, *data.get(1)?];
+        let (payload_len, mut offset) = match head[1] & 0x7f {
+            126 => (
+                u16::from_be_bytes(data.get(2..4)?.try_into().ok()?) as usize,
+                4,
+            ),
+            127 => (
+                u64::from_be_bytes(data.get(2..10)?.try_into().ok()?) as usize,
+                10,
+            ),
+            len => (len as usize, 2),
+        };
+        let mask = if head[1] & 0x80 != 0 {
+            let key = data.get(offset..offset + 4)?.try_into().ok()?;
+            offset += 4;
+            Some(key)
+        } else {
+            None
+        };
+        let len = offset.checked_add(payload_len)?;
+        (data.len() >= len).then_some(Self {
+            head,
+            mask,
+            offset,
+            len,
+        })
+    }
+
+    fn fin(&self) -> bool {
+        self.head[0] & 0x80 != 0
+    }
+
+    fn opcode(&self) -> u8 {
+        self.head[0] & 0x0f
+    }
+
+    /// The unmasked payload of the frame in `bytes`.
+    fn payload(&self, bytes: &[u8]) -> Vec<u8> {
+        let mut payload = bytes[self.offset..self.len].to_vec();
+        if let Some(mask) = self.mask {
+            apply_mask(&mut payload, mask);
+        }
+        payload
+    }
+}
+
+/// A frame header with first byte `first` for a payload of `len` bytes.
+fn frame_head(first: u8, len: usize, mask: Option<[u8; 4]>) -> Vec<u8> {
+    let mut head = vec![first];
+    let masked = if mask.is_some() { 0x80 } else { 0 };
+    match len {
+        0..=125 => head.push(masked | len as u8),
+        126..=0xffff => {
+            head.push(masked | 126);
+            head.extend((len as u16).to_be_bytes());
+        }
+        _ => {
+            head.push(masked | 127);
+            head.extend((len as u64).to_be_bytes());
+        }
+    }
+    if let Some(mask) = mask {
+        head.extend(mask);
+    }
+    head
+}
+
+fn apply_mask(data: &mut [u8], mask: [u8; 4]) {
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+}
+
+/// Compress one message, without the tail.
+fn deflate(compress: &mut Compress, data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len() / 2 + 64);
+    let mut input = data;
+    loop {
+        if out.len() == out.capacity() {
+            out.reserve(out.capacity().max(64));
+        }
+        let before = compress.total_in();
+        compress
+            .compress_vec(input, &mut out, FlushCompress::Sync)
+            .map_err(io::Error::other)?;
+        input = &input[(compress.total_in() - before) as usize..];
+        // Done once all input is in and the flush fit
+        if input.is_empty() && out.len() < out.capacity() {
+            break;
+        }
+    }
+    if out.ends_with(&TAIL) {
+        out.truncate(out.len() - TAIL.len());
+    }
+    Ok(out)
+}
+
+/// Inflate one frame's worth of a message.
+fn inflate(decompress: &mut Decompress, data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len() * 4 + 64);
+    let mut input = data;
+    loop {
+        if out.len() == out.capacity() {
+            out.reserve(out.capacity().max(64));
+        }
+        let (before, written) = (decompress.total_in(), out.len());
+        let status = decompress
+            .decompress_vec(input, &mut out, FlushDecompress::Sync)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let read = (decompress.total_in() - before) as usize;
+        input = &input[read..];
+        if status == Status::StreamEnd {
+            // A final block ends the stream; what follows starts a new one
+            decompress.reset(false);
+        } else if input.is_empty() && out.len() < out.capacity() {
+            break;
+        } else if read == 0 && out.len() == written && out.len() < out.capacity() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "truncated deflate data",
+            ));
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn negotiated_stream(
+        server_no_context_takeover: bool,
+        client_no_context_takeover: bool,
+    ) -> Deflate<()> {
+        let mut stream = Deflate::new((), true);
+        stream.state = State::Deflate(Params {
+            server_no_context_takeover,
+            client_no_context_takeover,
+        });
+        stream
+    }
+
+    /// A frame as tungstenite would write it: one masked frame per message.
+    fn client_frame(text: &[u8]) -> Vec<u8> {
+        let mask = [1, 2, 3, 4];
+        let mut payload = text.to_vec();
+        apply_mask(&mut payload, mask);
+        let mut frame = frame_head(0x81, payload.len(), Some(mask));
+        frame.extend(payload);
+        frame
+    }
+
+    /// The first frame in `data` and its unmasked payload, removed.
+    fn take_frame(data: &mut Vec<u8>) -> (Frame, Vec<u8>) {
+        let frame = Frame::parse(data).expect("a whole frame");
+        let payload = frame.payload(data);
+        data.drain(..frame.len);
+        (frame, payload)
+    }
+
+    fn inflate_message(decompress: &mut Decompress, payload: &[u8]) -> Vec<u8> {
+        inflate(decompress, &[payload, &TAIL].concat()).unwrap()
+    }
+
+    #[test]
+    fn outgoing_messages_are_compressed_without_the_tail() {
+        let mut stream = negotiated_stream(false, false);
+        stream.pending = client_frame(b"hello hello hello");
+        stream.encode().unwrap();
+        let (frame, payload) = take_frame(&mut stream.out);
+        assert_eq!(frame.head[0], 0x81 | RSV1);
+        assert!(frame.mask.is_some());
+        assert!(!payload.ends_with(&TAIL));
+        let mut decompress = Decompress::new(false);
+        assert_eq!(
+            inflate_message(&mut decompress, &payload),
+            b"hello hello hello"
+        );
+    }
+
+    #[test]
+    fn incoming_messages_get_the_tail_back() {
+        let compressed = deflate(
+            &mut Compress::new(Compression::default(), false),
+            b"hi there",
+        )
+        .unwrap();
+        assert!(!compressed.ends_with(&TAIL));
+        let mut stream = negotiated_stream(false, false);
+        stream.raw = frame_head(0x81 | RSV1, compressed.len(), None);
+        stream.raw.extend(&compressed);
+        assert!(stream.decode().unwrap());
+        let (frame, payload) = take_frame(&mut stream.decoded);
+        assert_eq!(frame.head[0], 0x81);
+        assert_eq!(payload, b"hi there");
+    }
+
+    #[test]
+    fn fragmented_messages_inflate_across_frames() {
+        let text = b"a message long enough to split across two frames".repeat(4);
+        let compressed = deflate(&mut Compress::new(Compression::default(), false), &text).unwrap();
+        let (first, second) = compressed.split_at(compressed.len() / 2);
+        let mut stream = negotiated_stream(false, false);
+        // Only the first frame carries RSV1; the continuation has FIN
+        stream.raw = frame_head(0x01 | RSV1, first.len(), None);
+        stream.raw.extend(first);
+        stream.raw.extend(frame_head(0x80, second.len(), None));
+        stream.raw.extend(second);
+        assert!(stream.decode().unwrap());
+        assert!(stream.decode().unwrap());
+        let (start, mut inflated) = take_frame(&mut stream.decoded);
+        let (end, rest) = take_frame(&mut stream.decoded);
+        assert_eq!((start.head[0], end.head[0]), (0x01, 0x80));
+        inflated.extend(rest);
+        assert_eq!(inflated, text);
+    }
+
+    #[test]
+    fn server_no_context_takeover_inflates_each_message_fresh() {
+        let mut stream = negotiated_stream(true, false);
+        for _ in 0..2 {
+            let compressed = deflate(
+                &mut Compress::new(Compression::default(), false),
+                b"same words",
+            )
+            .unwrap();
+            stream.raw = frame_head(0x81 | RSV1, compressed.len(), None);
+            stream.raw.extend(&compressed);
+            assert!(stream.decode().unwrap());
+            assert_eq!(take_frame(&mut stream.decoded).1, b"same words");
+        }
+    }
+
+    #[test]
+    fn context_takeover_shares_the_window_between_messages() {
+        let mut server = Compress::new(Compression::default(), false);
+        let mut stream = negotiated_stream(false, false);
+        for _ in 0..2 {
+            let compressed = deflate(&mut server, b"same words").unwrap();
+            stream.raw = frame_head(0x81 | RSV1, compressed.len(), None);
+            stream.raw.extend(&compressed);
+            assert!(stream.decode().unwrap());
+            assert_eq!(take_frame(&mut stream.decoded).1, b"same words");
+        }
+    }
+
+    #[test]
+    fn client_no_context_takeover_compresses_each_message_alone() {
+        let sizes = |client_no_context_takeover| {
+            let mut stream = negotiated_stream(false, client_no_context_takeover);
+            stream.pending = [client_frame(b"same words"), client_frame(b"same words")].concat();
+            stream.encode().unwrap();
+            let first = take_frame(&mut stream.out).1;
+            let second = take_frame(&mut stream.out).1;
+            (first.len(), second.len())
+        };
+        let (first, second) = sizes(true);
+        assert_eq!(first, second);
+        let (first, second) = sizes(false);
+        assert!(second < first);
+    }
+
+    #[test]
+    fn response_parameters_are_read() {
+        let head = "HTTP/1.1 101 Switching Protocols\r\n\
+                    sec-websocket-extensions: permessage-deflate; client_no_context_takeover\r\n\r\n";
+        let params = negotiated(head).unwrap();
+        assert!(params.client_no_context_takeover);
+        assert!(!params.server_no_context_takeover);
+        assert!(negotiated("HTTP/1.1 101 Switching Protocols\r\n\r\n").is_none());
+    }
+}
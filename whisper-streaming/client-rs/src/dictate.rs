@@ -0,0 +1,58 @@
+//! Dictation (--dictate): final transcripts are typed as synthetic
+//! keystrokes into whichever window has focus. Typing happens on its own
+//! thread, since the platform input handles aren't all `Send` and typing a
+//! long transcript takes a while.
+
+use anyhow::Result;
+use std::sync::mpsc;
+
+/// Queue of text to type; clones share the one keyboard.
+#[derive(Clone)]
+pub struct Dictation {
+    tx: mpsc::Sender<String>,
+}
+
+impl Dictation {
+    #[cfg(feature = "dictation")]
+    pub fn start() -> Result<Self> {
+        use enigo::{Enigo, Keyboard, Settings};
+
+        use crate::theme;
+
+        let (tx, rx) = mpsc::channel::<String>();
+        let (ready_tx, ready_rx) = mpsc::sync_channel(1);
+        std::thread::spawn(move || {
+            let mut enigo = match Enigo::new(&Settings::default()) {
+                Ok(enigo) => {
+                    let _ = ready_tx.send(Ok(()));
+                    enigo
+                }
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e));
+                    return;
+                }
+            };
+            for text in rx {
+                if let Err(e) = enigo.text(&text) {
+                    let warning = format!("[dictate] Cannot type: {}", e);
+                    say!("{}", theme::paint(theme::Role::Warning, &warning));
+                }
+            }
+        });
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok(Self { tx }),
+            Ok(Err(e)) => anyhow::bail!("Cannot simulate keystrokes: {}", e),
+            Err(_) => anyhow::bail!("Cannot simulate keystrokes"),
+        }
+    }
+
+    #[cfg(not(feature = "dictation"))]
+    pub fn start() -> Result<Self> {
+        anyhow::bail!("--dictate needs a build with `--features dictation`")
+    }
+
+    /// Type a final transcript, with a space to keep it apart from the next.
+    pub fn type_text(&self, text: &str) {
+        let _ = self.tx.send(format!("{} ", text));
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::VecDeque;
+
+use nnnoiseless::DenoiseState;
+
+use super::{Resampler, ResamplerKind, ResamplerQuality, Stage};
+use crate::theme;
+
+/// RNNoise denoising. The model runs on 10ms frames at 48kHz, so audio is
+/// band-limited resampled up, denoised frame by frame and brought back.
+/// Buffering whole frames delays the output by about 15ms.
+pub struct Denoise {
+    state: Box<DenoiseState<'static>>,
+    up: Resampler,
+    down: Resampler,
+    /// 10ms at the chunk rate, what `up` takes per call
+    frame_len: usize,
+    /// Waiting for a full frame at the chunk rate
+    input: VecDeque<f32>,
+    /// Upsampled, waiting for a full model frame
+    upsampled: Vec<f32>,
+    /// Denoised and back at the chunk rate
+    output: VecDeque<f32>,
+    /// Set by a resampler error, after which audio passes through as is
+    failed: bool,
+}
+
+impl Denoise {
+    const MODEL_RATE: u32 = 48000;
+
+    pub fn new(sample_rate: u32) -> anyhow::Result<Self> {
+        let frame_len = (sample_rate / 100) as usize;
+        let resampler = |from, to, chunk| {
+            Resampler::new(
+                ResamplerKind::Sinc,
+                ResamplerQuality::Balanced,
+                from,
+                to,
+                chunk,
+            )
+        };
+        Ok(Self {
+            state: DenoiseState::new(),
+            up: resampler(sample_rate, Self::MODEL_RATE, frame_len)?,
+            down: resampler(Self::MODEL_RATE, sample_rate, DenoiseState::FRAME_SIZE)?,
+            frame_len,
+            input: VecDeque::new(),
+            upsampled: Vec::new(),
+            output: VecDeque::new(),
+            failed: false,
+        })
+    }
+
+    /// Run every complete frame through the model.
+    fn drain(&mut self) -> anyhow::Result<()> {
+        while self.input.len() >= self.frame_len {
+            let frame: Vec<f32> = self.input.drain(..self.frame_len).collect();
+            self.upsampled.extend(self.up.process(&frame)?);
+        }
+        let done = self.upsampled.len() - self.upsampled.len() % DenoiseState::FRAME_SIZE;
+        let mut denoised = [0.0; DenoiseState::FRAME_SIZE];
+        for frame in self.upsampled[..done].chunks_exact(DenoiseState::FRAME_SIZE) {
+            // RNNoise expects i16-range floats
+            let scaled: Vec<f32> = frame.iter().map(|s| s * 32768.0).collect();
+            self.state.process_frame(&mut denoised, &scaled);
+            denoised.iter_mut().for_each(|s| *s /= 32768.0);
+            self.output.extend(self.down.process(&denoised)?);
+        }
+        self.upsampled.drain(..done);
+        Ok(())
+    }
+}
+
+impl Stage for Denoise {
+    fn process(&mut self, samples: &mut [f32]) {
+        if self.failed {
+            return;
+        }
+        self.input.extend(samples.iter().copied());
+        if let Err(e) = self.drain() {
+            // Reported once: this runs for every chunk
+            let warning = format!("[denoise] {}; denoising is off", e);
+            say!("{}", theme::paint(theme::Role::Warning, &warning));
+            self.failed = true;
+            return;
+        }
+        // Until the first frames come through, the delay is filled with silence
+        let missing = samples.len().saturating_sub(self.output.len());
+        let (silence, ready) = samples.split_at_mut(missing);
+        silence.fill(0.0);
+        let denoised = self.output.drain(..ready.len());
+        ready.iter_mut().zip(denoised).for_each(|(s, d)| *s = d);
+    }
+}
@@ -0,0 +1,203 @@
+use super::{calculate_energy, db_to_linear, Stage};
+
+/// Fixed digital gain.
+pub struct Gain {
+    factor: f32,
+}
+
+impl Gain {
+    pub fn new(gain_db: f32) -> Self {
+        Self {
+            factor: db_to_linear(gain_db),
+        }
+    }
+}
+
+impl Stage for Gain {
+    fn process(&mut self, samples: &mut [f32]) {
+        for s in samples.iter_mut() {
+            *s *= self.factor;
+        }
+    }
+}
+
+/// Automatic gain control: tracks chunk RMS and adapts gain so speech sits
+/// near `target_rms`. Chunks below `floor_rms` hold the current gain instead
+/// of boosting background noise up to speech level.
+pub struct Agc {
+    sample_rate: u32,
+    target_rms: f32,
+    floor_rms: f32,
+    max_gain: f32,
+    gain: f32,
+    attack_ms: f32,
+    release_ms: f32,
+}
+
+impl Agc {
+    pub fn new(sample_rate: u32, target_rms: f32, floor_rms: f32, max_gain_db: f32) -> Self {
+        Self {
+            sample_rate,
+            target_rms,
+            floor_rms,
+            max_gain: db_to_linear(max_gain_db),
+            gain: 1.0,
+            attack_ms: 50.0,
+            release_ms: 500.0,
+        }
+    }
+}
+
+impl Stage for Agc {
+    fn process(&mut self, samples: &mut [f32]) {
+        let rms = calculate_energy(samples);
+        if rms > self.floor_rms {
+            let desired = (self.target_rms / rms).clamp(1.0 / self.max_gain, self.max_gain);
+            // Back off quickly on loud input, ramp up slowly on quiet input
+            let tau_ms = if desired < self.gain {
+                self.attack_ms
+            } else {
+                self.release_ms
+            };
+            let chunk_ms = samples.len() as f32 * 1000.0 / self.sample_rate as f32;
+            let coeff = 1.0 - (-chunk_ms / tau_ms).exp();
+            self.gain += (desired - self.gain) * coeff;
+        }
+        for s in samples.iter_mut() {
+            *s *= self.gain;
+        }
+    }
+}
+
+/// Noise gate with attack, hold and release. Audio below the threshold is
+/// faded out, and the gate state replaces a bare energy comparison in speech
+/// detection.
+pub struct NoiseGate {
+    threshold_sq: f32,
+    env_coeff: f32,
+    attack_step: f32,
+    release_step: f32,
+    hold_samples: u32,
+    env: f32,
+    gain: f32,
+    hold_left: u32,
+}
+
+impl NoiseGate {
+    /// RMS envelope time constant
+    const ENVELOPE_MS: f32 = 10.0;
+
+    pub fn new(
+        sample_rate: u32,
+        threshold: f32,
+        attack_ms: f32,
+        hold_ms: f32,
+        release_ms: f32,
+    ) -> Self {
+        let per_ms = sample_rate as f32 / 1000.0;
+        Self {
+            threshold_sq: threshold * threshold,
+            env_coeff: 1.0 - (-1.0 / (Self::ENVELOPE_MS * per_ms)).exp(),
+            attack_step: 1.0 / (attack_ms * per_ms).max(1.0),
+            release_step: 1.0 / (release_ms * per_ms).max(1.0),
+            hold_samples: (hold_ms * per_ms) as u32,
+            env: 0.0,
+            gain: 0.0,
+            hold_left: 0,
+        }
+    }
+
+    /// Gate the chunk in place; returns whether the gate was open at any point.
+    pub fn process(&mut self, samples: &mut [f32]) -> bool {
+        let mut opened = false;
+        for s in samples.iter_mut() {
+            self.env += (*s * *s - self.env) * self.env_coeff;
+            let open = if self.env >= self.threshold_sq {
+                self.hold_left = self.hold_samples;
+                true
+            } else if self.hold_left > 0 {
+                self.hold_left -= 1;
+                true
+            } else {
+                false
+            };
+            self.gain = if open {
+                (self.gain + self.attack_step).min(1.0)
+            } else {
+                (self.gain - self.release_step).max(0.0)
+            };
+            opened |= open;
+            *s *= self.gain;
+        }
+        opened
+    }
+}
+
+/// Background noise level estimate, used to derive the speech energy
+/// threshold. Follows drops in level quickly and rises slowly, so brief
+/// non-speech sounds don't drag the threshold up.
+pub struct NoiseFloor {
+    sample_rate: u32,
+    floor: f32,
+    margin: f32,
+}
+
+impl NoiseFloor {
+    const RISE_MS: f32 = 10_000.0;
+    const FALL_MS: f32 = 500.0;
+    /// Lowest floor tracked, so digital silence doesn't zero the threshold
+    const MIN_FLOOR: f32 = 1e-4;
+
+    pub fn new(sample_rate: u32, initial_threshold: f32, margin_db: f32) -> Self {
+        let margin = db_to_linear(margin_db);
+        Self {
+            sample_rate,
+            floor: (initial_threshold / margin).max(Self::MIN_FLOOR),
+            margin,
+        }
+    }
+
+    /// Feed the energy of a chunk that contained no speech.
+    pub fn update(&mut self, energy: f32, chunk_len: usize) {
+        let tau_ms = if energy < self.floor {
+            Self::FALL_MS
+        } else {
+            Self::RISE_MS
+        };
+        let chunk_ms = chunk_len as f32 * 1000.0 / self.sample_rate as f32;
+        let coeff = 1.0 - (-chunk_ms / tau_ms).exp();
+        self.floor = (self.floor + (energy - self.floor) * coeff).max(Self::MIN_FLOOR);
+    }
+
+    pub fn threshold(&self) -> f32 {
+        self.floor * self.margin
+    }
+}
+
+/// Soft-knee limiter: samples above `threshold` are compressed with a tanh
+/// curve that approaches full scale asymptotically, so boosted audio never
+/// hard-clips.
+pub struct SoftLimiter {
+    threshold: f32,
+}
+
+impl SoftLimiter {
+    pub fn new(threshold: f32) -> Self {
+        Self {
+            threshold: threshold.clamp(0.0, 0.999),
+        }
+    }
+}
+
+impl Stage for SoftLimiter {
+    fn process(&mut self, samples: &mut [f32]) {
+        let headroom = 1.0 - self.threshold;
+        for s in samples.iter_mut() {
+            let magnitude = s.abs();
+            if magnitude > self.threshold {
+                let over = (magnitude - self.threshold) / headroom;
+                *s = s.signum() * (self.threshold + headroom * over.tanh());
+            }
+        }
+    }
+}
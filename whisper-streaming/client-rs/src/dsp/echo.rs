@@ -0,0 +1,104 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use super::Stage;
+
+/// Far-end signal the client is currently playing, resampled to the capture
+/// processing rate. Playback pushes samples as they are handed to the output
+/// device and the echo canceller consumes them in step with the microphone.
+#[derive(Clone)]
+pub struct EchoReference {
+    sample_rate: u32,
+    buffer: Arc<Mutex<VecDeque<f32>>>,
+}
+
+impl EchoReference {
+    /// Samples older than this are dropped if capture falls behind.
+    const MAX_BUFFER_MS: u32 = 2000;
+
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            buffer: Arc::default(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn push(&self, samples: &[f32]) {
+        let max_len = (self.sample_rate * Self::MAX_BUFFER_MS / 1000) as usize;
+        let mut buffer = self.buffer.lock().unwrap();
+        buffer.extend(samples);
+        let excess = buffer.len().saturating_sub(max_len);
+        buffer.drain(..excess);
+    }
+
+    /// Next `n` far-end samples, zero-padded when nothing is playing.
+    fn take(&self, n: usize) -> Vec<f32> {
+        let mut buffer = self.buffer.lock().unwrap();
+        let available = n.min(buffer.len());
+        let mut out: Vec<f32> = buffer.drain(..available).collect();
+        out.resize(n, 0.0);
+        out
+    }
+}
+
+/// Acoustic echo cancellation with a time-domain NLMS filter modelling the
+/// speaker→mic path. Adaptation freezes during double talk (Geigel detector)
+/// so the user's own voice doesn't get cancelled.
+pub struct EchoCanceller {
+    reference: EchoReference,
+    weights: Vec<f32>,
+    // Far-end history stored twice so the filter window is always contiguous
+    history: Vec<f32>,
+    pos: usize,
+    power: f32,
+}
+
+impl EchoCanceller {
+    const STEP_SIZE: f32 = 0.3;
+    const DOUBLE_TALK_RATIO: f32 = 0.5;
+
+    pub fn new(reference: EchoReference, tail_ms: u32) -> Self {
+        let taps = (reference.sample_rate * tail_ms / 1000).max(1) as usize;
+        Self {
+            reference,
+            weights: vec![0.0; taps],
+            history: vec![0.0; taps * 2],
+            pos: 0,
+            power: 0.0,
+        }
+    }
+}
+
+impl Stage for EchoCanceller {
+    fn process(&mut self, samples: &mut [f32]) {
+        let far = self.reference.take(samples.len());
+        let taps = self.weights.len();
+
+        for (mic, &x) in samples.iter_mut().zip(&far) {
+            let oldest = self.history[self.pos];
+            self.power += x * x - oldest * oldest;
+            self.history[self.pos] = x;
+            self.history[self.pos + taps] = x;
+            self.pos = (self.pos + 1) % taps;
+
+            // Newest sample last: window[k] is x delayed by taps-1-k
+            let window = &self.history[self.pos..self.pos + taps];
+            let estimate: f32 = window.iter().zip(&self.weights).map(|(x, w)| x * w).sum();
+            let error = *mic - estimate;
+
+            let far_peak = window.iter().fold(0.0f32, |m, &v| m.max(v.abs()));
+            let double_talk = mic.abs() > Self::DOUBLE_TALK_RATIO * far_peak;
+            if !double_talk && self.power > 1e-6 {
+                let step = Self::STEP_SIZE * error / (self.power + 1e-6);
+                for (w, x) in self.weights.iter_mut().zip(window) {
+                    *w += step * x;
+                }
+            }
+            *mic = error;
+        }
+    }
+}
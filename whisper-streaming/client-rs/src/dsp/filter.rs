@@ -0,0 +1,89 @@
+use super::Stage;
+
+/// Second-order IIR filter (RBJ cookbook coefficients, transposed direct form II).
+pub struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    z1: f32,
+    z2: f32,
+}
+
+impl Biquad {
+    const BUTTERWORTH_Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+    /// Butterworth high-pass; removes DC offset and low-frequency rumble.
+    pub fn highpass(sample_rate: u32, cutoff_hz: f32) -> Self {
+        Self::highpass_q(sample_rate, cutoff_hz, Self::BUTTERWORTH_Q)
+    }
+
+    pub fn highpass_q(sample_rate: u32, cutoff_hz: f32, q: f32) -> Self {
+        let (cos_w, alpha) = Self::prewarp(sample_rate, cutoff_hz, q);
+        Self::from_coeffs(
+            (1.0 + cos_w) / 2.0,
+            -(1.0 + cos_w),
+            (1.0 + cos_w) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos_w,
+            1.0 - alpha,
+        )
+    }
+
+    /// Butterworth low-pass.
+    pub fn lowpass(sample_rate: u32, cutoff_hz: f32) -> Self {
+        let (cos_w, alpha) = Self::prewarp(sample_rate, cutoff_hz, Self::BUTTERWORTH_Q);
+        Self::from_coeffs(
+            (1.0 - cos_w) / 2.0,
+            1.0 - cos_w,
+            (1.0 - cos_w) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos_w,
+            1.0 - alpha,
+        )
+    }
+
+    pub fn high_shelf(sample_rate: u32, freq_hz: f32, gain_db: f32, q: f32) -> Self {
+        let a = 10f32.powf(gain_db / 40.0);
+        let (cos_w, alpha) = Self::prewarp(sample_rate, freq_hz, q);
+        let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+        Self::from_coeffs(
+            a * ((a + 1.0) + (a - 1.0) * cos_w + sqrt_a_alpha),
+            -2.0 * a * ((a - 1.0) + (a + 1.0) * cos_w),
+            a * ((a + 1.0) + (a - 1.0) * cos_w - sqrt_a_alpha),
+            (a + 1.0) - (a - 1.0) * cos_w + sqrt_a_alpha,
+            2.0 * ((a - 1.0) - (a + 1.0) * cos_w),
+            (a + 1.0) - (a - 1.0) * cos_w - sqrt_a_alpha,
+        )
+    }
+
+    fn prewarp(sample_rate: u32, freq_hz: f32, q: f32) -> (f32, f32) {
+        let w0 = 2.0 * std::f32::consts::PI * freq_hz / sample_rate as f32;
+        (w0.cos(), w0.sin() / (2.0 * q))
+    }
+
+    fn from_coeffs(b0: f32, b1: f32, b2: f32, a0: f32, a1: f32, a2: f32) -> Self {
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+}
+
+impl Stage for Biquad {
+    fn process(&mut self, samples: &mut [f32]) {
+        for s in samples.iter_mut() {
+            let x = *s;
+            let y = self.b0 * x + self.z1;
+            self.z1 = self.b1 * x - self.a1 * y + self.z2;
+            self.z2 = self.b2 * x - self.a2 * y;
+            *s = y;
+        }
+    }
+}
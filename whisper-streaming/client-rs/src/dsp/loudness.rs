@@ -0,0 +1,65 @@
+//! ITU-R BS.1770 / EBU R128 integrated loudness.
+
+use super::{db_to_linear, Biquad, Stage};
+
+const BLOCK_MS: u32 = 400;
+const STEP_MS: u32 = 100;
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = 10.0;
+
+/// Largest boost applied to very quiet utterances.
+const MAX_GAIN_DB: f32 = 20.0;
+/// Peak level never exceeded by normalization (-1 dBFS).
+const PEAK_CEILING: f32 = 0.891;
+
+/// Integrated loudness in LUFS, or `None` for silence.
+pub fn integrated_loudness(samples: &[f32], sample_rate: u32) -> Option<f64> {
+    // K-weighting: head-related high shelf followed by the RLB high-pass
+    let mut weighted = samples.to_vec();
+    Biquad::high_shelf(sample_rate, 1500.0, 4.0, std::f32::consts::FRAC_1_SQRT_2)
+        .process(&mut weighted);
+    Biquad::highpass_q(sample_rate, 38.0, 0.5).process(&mut weighted);
+
+    let block = (sample_rate * BLOCK_MS / 1000) as usize;
+    let step = (sample_rate * STEP_MS / 1000) as usize;
+    let mean_square =
+        |s: &[f32]| s.iter().map(|&x| (x as f64) * (x as f64)).sum::<f64>() / s.len() as f64;
+
+    // Clips shorter than one gating block are measured as a single block
+    let powers: Vec<f64> = if weighted.len() < block {
+        vec![mean_square(&weighted)]
+    } else {
+        (0..=(weighted.len() - block) / step)
+            .map(|i| mean_square(&weighted[i * step..i * step + block]))
+            .collect()
+    };
+
+    let lufs = |power: f64| -0.691 + 10.0 * power.log10();
+    let gated_mean = |threshold: f64| -> Option<f64> {
+        let kept: Vec<f64> = powers
+            .iter()
+            .copied()
+            .filter(|&p| lufs(p) > threshold)
+            .collect();
+        (!kept.is_empty()).then(|| kept.iter().sum::<f64>() / kept.len() as f64)
+    };
+
+    let relative_gate = lufs(gated_mean(ABSOLUTE_GATE_LUFS)?) - RELATIVE_GATE_LU;
+    gated_mean(relative_gate.max(ABSOLUTE_GATE_LUFS)).map(lufs)
+}
+
+/// Scale an utterance towards `target_lufs`, capped so peaks stay below -1 dBFS.
+pub fn normalize_loudness(samples: &mut [f32], sample_rate: u32, target_lufs: f32) {
+    let Some(measured) = integrated_loudness(samples, sample_rate) else {
+        return;
+    };
+    let peak = samples.iter().fold(0.0f32, |m, &s| m.max(s.abs()));
+    if peak == 0.0 {
+        return;
+    }
+    let gain =
+        db_to_linear((target_lufs - measured as f32).min(MAX_GAIN_DB)).min(PEAK_CEILING / peak);
+    for s in samples.iter_mut() {
+        *s *= gain;
+    }
+}
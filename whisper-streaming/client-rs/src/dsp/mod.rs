@@ -0,0 +1,83 @@
+//! Audio preprocessing applied to captured chunks before energy/VAD and transmission.
+
+use rand::Rng;
+
+mod denoise;
+mod dynamics;
+mod echo;
+mod filter;
+mod loudness;
+mod plugin;
+mod resample;
+mod spectral;
+
+pub use denoise::Denoise;
+pub use dynamics::{Agc, Gain, NoiseFloor, NoiseGate, SoftLimiter};
+pub use echo::{EchoCanceller, EchoReference};
+pub use filter::Biquad;
+pub use loudness::normalize_loudness;
+pub use plugin::Plugin;
+pub use resample::{resample, Resampler, ResamplerKind, ResamplerQuality};
+pub use spectral::{Dereverb, NoiseProfile, SpectralGate, SpectralSubtraction};
+
+/// A single processing step operating in place on a chunk of mono samples
+/// at the target sample rate.
+pub trait Stage: Send {
+    fn process(&mut self, samples: &mut [f32]);
+}
+
+/// Ordered chain of stages, applied to every chunk.
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Box<dyn Stage>>,
+}
+
+impl Pipeline {
+    pub fn push(&mut self, stage: impl Stage + 'static) {
+        self.stages.push(Box::new(stage));
+    }
+
+    pub fn push_boxed(&mut self, stage: Box<dyn Stage>) {
+        self.stages.push(stage);
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for stage in &mut self.stages {
+            stage.process(samples);
+        }
+    }
+}
+
+impl Stage for Pipeline {
+    fn process(&mut self, samples: &mut [f32]) {
+        Pipeline::process(self, samples);
+    }
+}
+
+pub fn db_to_linear(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+pub fn calculate_energy(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let sum_sq: f32 = samples.iter().map(|&s| s * s).sum();
+    (sum_sq / samples.len() as f32).sqrt()
+}
+
+/// Convert to i16 with symmetric scaling and rounding. With `dither`, TPDF
+/// noise of +/-1 LSB decorrelates quantization error from quiet signals.
+pub fn f32_to_i16(samples: &[f32], dither: bool) -> Vec<i16> {
+    let mut rng = rand::thread_rng();
+    samples
+        .iter()
+        .map(|&s| {
+            let mut scaled = s * 32767.0;
+            if dither {
+                scaled += rng.gen::<f32>() - rng.gen::<f32>();
+            }
+            scaled.round().clamp(-32768.0, 32767.0) as i16
+        })
+        .collect()
+}
@@ -0,0 +1,83 @@
+//! User-supplied processing stages loaded from a shared library, so custom
+//! DSP can be inserted into the audio path without rebuilding the client.
+//!
+//! A plugin exports three C functions:
+//!
+//! ```c
+//! void *whisper_dsp_create(uint32_t sample_rate);
+//! void whisper_dsp_process(void *state, float *samples, size_t len);
+//! void whisper_dsp_destroy(void *state);
+//! ```
+//!
+//! `whisper_dsp_process` modifies mono samples at the target rate in place.
+//! Calls for one state never overlap, but may come from different threads.
+
+use anyhow::{bail, Context, Result};
+use libloading::Library;
+use std::ffi::c_void;
+use std::path::Path;
+
+use super::Stage;
+
+type CreateFn = unsafe extern "C" fn(u32) -> *mut c_void;
+type ProcessFn = unsafe extern "C" fn(*mut c_void, *mut f32, usize);
+type DestroyFn = unsafe extern "C" fn(*mut c_void);
+
+pub struct Plugin {
+    state: *mut c_void,
+    process: ProcessFn,
+    destroy: DestroyFn,
+    // Keeps the function pointers above valid; dropped last
+    _library: Library,
+}
+
+// The plugin contract requires state to be usable from any thread
+unsafe impl Send for Plugin {}
+
+impl Plugin {
+    pub fn load(path: &Path, sample_rate: u32) -> Result<Self> {
+        // SAFETY: loading runs the library's initializers; plugins are
+        // trusted code chosen by the user
+        let library = unsafe { Library::new(path) }
+            .with_context(|| format!("Cannot load DSP plugin {}", path.display()))?;
+        let symbols = || -> Result<(CreateFn, ProcessFn, DestroyFn), libloading::Error> {
+            // SAFETY: the signatures are the documented plugin ABI
+            unsafe {
+                Ok((
+                    *library.get::<CreateFn>(b"whisper_dsp_create\0")?,
+                    *library.get::<ProcessFn>(b"whisper_dsp_process\0")?,
+                    *library.get::<DestroyFn>(b"whisper_dsp_destroy\0")?,
+                ))
+            }
+        };
+        let (create, process, destroy) =
+            symbols().with_context(|| format!("{} is not a DSP plugin", path.display()))?;
+
+        // SAFETY: per the plugin ABI
+        let state = unsafe { create(sample_rate) };
+        if state.is_null() {
+            bail!("DSP plugin {} failed to initialize", path.display());
+        }
+        Ok(Self {
+            state,
+            process,
+            destroy,
+            _library: library,
+        })
+    }
+}
+
+impl Stage for Plugin {
+    fn process(&mut self, samples: &mut [f32]) {
+        // SAFETY: state came from whisper_dsp_create and the buffer is valid
+        // for the duration of the call
+        unsafe { (self.process)(self.state, samples.as_mut_ptr(), samples.len()) }
+    }
+}
+
+impl Drop for Plugin {
+    fn drop(&mut self) {
+        // SAFETY: state is destroyed exactly once, before the library unloads
+        unsafe { (self.destroy)(self.state) }
+    }
+}
@@ -0,0 +1,110 @@
+use rubato::{
+    Resampler as _, SincFixedIn, SincInterpolationParameters, SincInterpolationType, WindowFunction,
+};
+
+/// Converts fixed-size device chunks to the target rate. Output length can
+/// vary slightly between calls for the sinc resampler.
+pub enum Resampler {
+    Linear { from_rate: u32, to_rate: u32 },
+    Sinc(Box<SincFixedIn<f32>>),
+}
+
+impl Resampler {
+    pub fn new(
+        kind: ResamplerKind,
+        quality: ResamplerQuality,
+        from_rate: u32,
+        to_rate: u32,
+        chunk_size: usize,
+    ) -> anyhow::Result<Self> {
+        if kind == ResamplerKind::Linear || from_rate == to_rate {
+            return Ok(Self::Linear { from_rate, to_rate });
+        }
+        let params = quality.parameters();
+        let ratio = to_rate as f64 / from_rate as f64;
+        let sinc = SincFixedIn::new(ratio, 1.0, params, chunk_size, 1)?;
+        Ok(Self::Sinc(Box::new(sinc)))
+    }
+
+    pub fn process(&mut self, samples: &[f32]) -> anyhow::Result<Vec<f32>> {
+        match self {
+            Self::Linear { from_rate, to_rate } => Ok(resample(samples, *from_rate, *to_rate)),
+            Self::Sinc(sinc) => {
+                let mut out = sinc.process(&[samples], None)?;
+                Ok(out.remove(0))
+            }
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResamplerKind {
+    /// Linear interpolation (cheap, aliases on downsampling)
+    Linear,
+    /// Band-limited windowed-sinc resampling
+    Sinc,
+}
+
+/// Trade-off between CPU cost and filter steepness for the sinc resampler.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResamplerQuality {
+    /// Short filter for low-power devices (e.g. Raspberry Pi)
+    Fast,
+    Balanced,
+    /// Long filter with cubic interpolation
+    Best,
+}
+
+impl ResamplerQuality {
+    fn parameters(self) -> SincInterpolationParameters {
+        let (sinc_len, f_cutoff, interpolation, window) = match self {
+            Self::Fast => (
+                32,
+                0.85,
+                SincInterpolationType::Linear,
+                WindowFunction::Hann2,
+            ),
+            Self::Balanced => (
+                128,
+                0.95,
+                SincInterpolationType::Linear,
+                WindowFunction::BlackmanHarris2,
+            ),
+            Self::Best => (
+                256,
+                0.95,
+                SincInterpolationType::Cubic,
+                WindowFunction::BlackmanHarris2,
+            ),
+        };
+        SincInterpolationParameters {
+            sinc_len,
+            f_cutoff,
+            oversampling_factor: 256,
+            interpolation,
+            window,
+        }
+    }
+}
+
+pub fn resample(samples: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if from_rate == to_rate {
+        return samples.to_vec();
+    }
+    let ratio = to_rate as f64 / from_rate as f64;
+    let output_len = (samples.len() as f64 * ratio) as usize;
+    (0..output_len)
+        .map(|i| {
+            let src_idx = i as f64 / ratio;
+            let idx = src_idx.floor() as usize;
+            let frac = src_idx.fract() as f32;
+            if idx + 1 < samples.len() {
+                samples[idx] * (1.0 - frac) + samples[idx + 1] * frac
+            } else if idx < samples.len() {
+                samples[idx]
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
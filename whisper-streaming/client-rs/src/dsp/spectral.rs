@@ -0,0 +1,327 @@
+//! STFT-based spectral processing: noise profiles, spectral subtraction and
+//! late-reverb suppression.
+
+use anyhow::{bail, Context, Result};
+use realfft::num_complex::Complex;
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::Stage;
+
+pub const FFT_SIZE: usize = 512;
+const HOP: usize = FFT_SIZE / 2;
+
+/// Square-root Hann window: applied on analysis and synthesis it gives
+/// perfect reconstruction at 50% overlap.
+fn sqrt_hann() -> Vec<f32> {
+    (0..FFT_SIZE)
+        .map(|i| {
+            let phase = 2.0 * std::f32::consts::PI * i as f32 / FFT_SIZE as f32;
+            (0.5 - 0.5 * phase.cos()).sqrt()
+        })
+        .collect()
+}
+
+/// Average magnitude spectrum of room tone.
+#[derive(Serialize, Deserialize)]
+pub struct NoiseProfile {
+    pub sample_rate: u32,
+    pub fft_size: usize,
+    pub magnitudes: Vec<f32>,
+}
+
+impl NoiseProfile {
+    /// Estimate the profile from a recording of background noise only.
+    pub fn learn(samples: &[f32], sample_rate: u32) -> Result<Self> {
+        if samples.len() < FFT_SIZE {
+            bail!(
+                "Need at least {} samples to learn a noise profile",
+                FFT_SIZE
+            );
+        }
+        let window = sqrt_hann();
+        let fft = RealFftPlanner::<f32>::new().plan_fft_forward(FFT_SIZE);
+        let mut input = fft.make_input_vec();
+        let mut spectrum = fft.make_output_vec();
+        let mut magnitudes = vec![0.0; spectrum.len()];
+        let mut frames = 0;
+
+        for start in (0..=samples.len() - FFT_SIZE).step_by(HOP) {
+            for ((x, &s), &w) in input.iter_mut().zip(&samples[start..]).zip(&window) {
+                *x = s * w;
+            }
+            fft.process(&mut input, &mut spectrum)?;
+            for (m, c) in magnitudes.iter_mut().zip(&spectrum) {
+                *m += c.norm();
+            }
+            frames += 1;
+        }
+        magnitudes.iter_mut().for_each(|m| *m /= frames as f32);
+
+        Ok(Self {
+            sample_rate,
+            fft_size: FFT_SIZE,
+            magnitudes,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read noise profile {}", path.display()))?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string(self)?)
+            .with_context(|| format!("Cannot write noise profile {}", path.display()))
+    }
+}
+
+/// 50%-overlap STFT analysis/resynthesis that hands each frame's spectrum
+/// to a callback. Adds one FFT frame (32ms at 16kHz) of latency.
+struct Stft {
+    window: Vec<f32>,
+    forward: Arc<dyn RealToComplex<f32>>,
+    inverse: Arc<dyn ComplexToReal<f32>>,
+    input: Vec<f32>,
+    overlap: Vec<f32>,
+    output: VecDeque<f32>,
+}
+
+impl Stft {
+    fn new() -> Self {
+        let mut planner = RealFftPlanner::<f32>::new();
+        Self {
+            window: sqrt_hann(),
+            forward: planner.plan_fft_forward(FFT_SIZE),
+            inverse: planner.plan_fft_inverse(FFT_SIZE),
+            input: Vec::with_capacity(FFT_SIZE * 2),
+            overlap: vec![0.0; FFT_SIZE],
+            output: std::iter::repeat_n(0.0, FFT_SIZE).collect(),
+        }
+    }
+
+    fn process(&mut self, samples: &mut [f32], mut modify: impl FnMut(&mut [Complex<f32>])) {
+        self.input.extend_from_slice(samples);
+        while self.input.len() >= FFT_SIZE {
+            self.process_frame(&mut modify);
+        }
+        for s in samples.iter_mut() {
+            *s = self.output.pop_front().unwrap_or(0.0);
+        }
+    }
+
+    fn process_frame(&mut self, modify: &mut impl FnMut(&mut [Complex<f32>])) {
+        let mut frame: Vec<f32> = self.input[..FFT_SIZE]
+            .iter()
+            .zip(&self.window)
+            .map(|(s, w)| s * w)
+            .collect();
+        let mut spectrum = self.forward.make_output_vec();
+        // Buffer lengths always match the plan, so these cannot fail
+        let _ = self.forward.process(&mut frame, &mut spectrum);
+
+        modify(&mut spectrum);
+        // Real-input FFT requires purely real DC and Nyquist bins
+        spectrum[0].im = 0.0;
+        if let Some(last) = spectrum.last_mut() {
+            *last = Complex::new(last.re, 0.0);
+        }
+
+        let _ = self.inverse.process(&mut spectrum, &mut frame);
+        let scale = 1.0 / FFT_SIZE as f32;
+        for ((o, s), w) in self.overlap.iter_mut().zip(&frame).zip(&self.window) {
+            *o += s * w * scale;
+        }
+
+        self.output.extend(&self.overlap[..HOP]);
+        self.overlap.copy_within(HOP.., 0);
+        self.overlap[FFT_SIZE - HOP..].fill(0.0);
+        self.input.drain(..HOP);
+    }
+}
+
+/// Subtracts a learned noise spectrum from every STFT frame.
+pub struct SpectralSubtraction {
+    noise: Vec<f32>,
+    stft: Stft,
+}
+
+impl SpectralSubtraction {
+    /// Over-subtraction factor; values above 1 suppress musical noise
+    const OVERSUBTRACT: f32 = 2.0;
+    /// Minimum fraction of each bin's magnitude that is kept
+    const SPECTRAL_FLOOR: f32 = 0.05;
+
+    pub fn new(profile: NoiseProfile, sample_rate: u32) -> Result<Self> {
+        if profile.fft_size != FFT_SIZE || profile.sample_rate != sample_rate {
+            bail!(
+                "Noise profile was learned at {}Hz/{} bins, expected {}Hz/{}",
+                profile.sample_rate,
+                profile.fft_size,
+                sample_rate,
+                FFT_SIZE
+            );
+        }
+        Ok(Self {
+            noise: profile.magnitudes,
+            stft: Stft::new(),
+        })
+    }
+}
+
+impl Stage for SpectralSubtraction {
+    fn process(&mut self, samples: &mut [f32]) {
+        let noise = &self.noise;
+        self.stft.process(samples, |spectrum| {
+            for (bin, &noise) in spectrum.iter_mut().zip(noise) {
+                let mag = bin.norm();
+                if mag > 0.0 {
+                    let cleaned =
+                        (mag - Self::OVERSUBTRACT * noise).max(Self::SPECTRAL_FLOOR * mag);
+                    *bin *= cleaned / mag;
+                }
+            }
+        });
+    }
+}
+
+/// Late-reverb suppression after Lebart et al.: the reverberant tail in each
+/// bin is predicted as the power from ~50ms earlier, decayed according to
+/// the room's RT60, and attenuated by spectral subtraction. Early
+/// reflections, which carry most of the speech energy, are left intact.
+pub struct Dereverb {
+    stft: Stft,
+    /// Smoothed power spectra of the most recent frames, oldest first
+    history: VecDeque<Vec<f32>>,
+    delay_frames: usize,
+    decay: f32,
+}
+
+impl Dereverb {
+    /// Boundary between early reflections and late reverberation
+    const LATE_DELAY_MS: f32 = 50.0;
+    /// Smoothing of the power history the late tail is predicted from
+    const SMOOTHING: f32 = 0.6;
+    /// Minimum gain per bin, limits musical noise
+    const GAIN_FLOOR: f32 = 0.1;
+
+    pub fn new(sample_rate: u32, rt60_ms: f32) -> Self {
+        let hop_secs = HOP as f32 / sample_rate as f32;
+        let delay_frames = ((Self::LATE_DELAY_MS / 1000.0 / hop_secs).round() as usize).max(1);
+        // Energy decays 60dB over RT60: exp(-2 * delta * t), delta = 3 ln(10) / RT60
+        let delta = 3.0 * std::f32::consts::LN_10 / (rt60_ms.max(1.0) / 1000.0);
+        let decay = (-2.0 * delta * delay_frames as f32 * hop_secs).exp();
+        Self {
+            stft: Stft::new(),
+            history: VecDeque::with_capacity(delay_frames + 1),
+            delay_frames,
+            decay,
+        }
+    }
+}
+
+impl Stage for Dereverb {
+    fn process(&mut self, samples: &mut [f32]) {
+        let history = &mut self.history;
+        let (delay_frames, decay) = (self.delay_frames, self.decay);
+        self.stft.process(samples, |spectrum| {
+            let power: Vec<f32> = match history.back() {
+                Some(prev) => spectrum
+                    .iter()
+                    .zip(prev)
+                    .map(|(c, p)| Self::SMOOTHING * p + (1.0 - Self::SMOOTHING) * c.norm_sqr())
+                    .collect(),
+                None => spectrum.iter().map(|c| c.norm_sqr()).collect(),
+            };
+            if history.len() == delay_frames {
+                let late = &history[0];
+                for (bin, &l) in spectrum.iter_mut().zip(late) {
+                    let p = bin.norm_sqr();
+                    if p > 0.0 {
+                        let gain = (1.0 - decay * l / p).max(Self::GAIN_FLOOR);
+                        *bin *= gain.sqrt();
+                    }
+                }
+                history.pop_front();
+            }
+            history.push_back(power);
+        });
+    }
+}
+
+/// Coarse spectral shape of one chunk.
+pub struct SpectralFeatures {
+    /// Power-weighted mean frequency
+    pub centroid_hz: f32,
+    /// Geometric / arithmetic mean of the power spectrum: ~0 for tonal
+    /// sounds, ~1 for white-noise-like ones
+    pub flatness: f32,
+}
+
+/// Rejects chunks whose spectrum doesn't look like voiced speech, such as
+/// keyboard clicks and door slams that pass the energy threshold.
+pub struct SpectralGate {
+    sample_rate: u32,
+    planner: RealFftPlanner<f32>,
+    min_centroid_hz: f32,
+    max_centroid_hz: f32,
+    max_flatness: f32,
+}
+
+impl SpectralGate {
+    pub fn new(
+        sample_rate: u32,
+        min_centroid_hz: f32,
+        max_centroid_hz: f32,
+        max_flatness: f32,
+    ) -> Self {
+        Self {
+            sample_rate,
+            planner: RealFftPlanner::new(),
+            min_centroid_hz,
+            max_centroid_hz,
+            max_flatness,
+        }
+    }
+
+    pub fn analyze(&mut self, samples: &[f32]) -> SpectralFeatures {
+        let fft_size = samples.len().next_power_of_two().max(2);
+        let fft = self.planner.plan_fft_forward(fft_size);
+        let mut input = fft.make_input_vec();
+        let n = samples.len() as f32;
+        for (i, (x, &s)) in input.iter_mut().zip(samples).enumerate() {
+            let w = 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / n).cos();
+            *x = s * w;
+        }
+        let mut spectrum = fft.make_output_vec();
+        let _ = fft.process(&mut input, &mut spectrum);
+
+        // Skip the DC bin
+        let bin_hz = self.sample_rate as f32 / fft_size as f32;
+        let power: Vec<f32> = spectrum[1..].iter().map(|c| c.norm_sqr() + 1e-12).collect();
+        let total: f32 = power.iter().sum();
+        let centroid_hz = power
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (i + 1) as f32 * bin_hz * p)
+            .sum::<f32>()
+            / total;
+        let log_mean = power.iter().map(|p| p.ln()).sum::<f32>() / power.len() as f32;
+        let flatness = log_mean.exp() / (total / power.len() as f32);
+
+        SpectralFeatures {
+            centroid_hz,
+            flatness,
+        }
+    }
+
+    pub fn is_speech_like(&mut self, samples: &[f32]) -> bool {
+        let features = self.analyze(samples);
+        (self.min_centroid_hz..=self.max_centroid_hz).contains(&features.centroid_hz)
+            && features.flatness <= self.max_flatness
+    }
+}
@@ -0,0 +1,60 @@
+//! Endpointing on interim transcripts: an utterance is final once the text
+//! the server returns for it stops changing.
+
+pub struct PartialEndpointer {
+    interval_ms: u32,
+    stable_ms: u32,
+    last_request_ms: u32,
+    text: Option<String>,
+    unchanged_since_ms: u32,
+}
+
+impl PartialEndpointer {
+    pub fn new(interval_ms: u32, stable_ms: u32) -> Self {
+        Self {
+            interval_ms,
+            stable_ms,
+            last_request_ms: 0,
+            text: None,
+            unchanged_since_ms: 0,
+        }
+    }
+
+    /// Whether an interim transcript is due for `duration_ms` of utterance audio.
+    pub fn due(&self, duration_ms: u32) -> bool {
+        duration_ms >= self.last_request_ms + self.interval_ms
+    }
+
+    /// Record the transcript of the first `duration_ms` of audio. Returns true
+    /// once it has been unchanged for the stable period.
+    pub fn update(&mut self, text: &str, duration_ms: u32) -> bool {
+        self.last_request_ms = duration_ms;
+        let text = normalize(text);
+        if self.text.as_deref() != Some(text.as_str()) {
+            self.text = Some(text);
+            self.unchanged_since_ms = duration_ms;
+            return false;
+        }
+        duration_ms - self.unchanged_since_ms >= self.stable_ms
+    }
+
+    pub fn reset(&mut self) {
+        self.last_request_ms = 0;
+        self.text = None;
+        self.unchanged_since_ms = 0;
+    }
+}
+
+/// Case and punctuation often flip between partials without the words changing.
+pub fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .map(|w| {
+            w.chars()
+                .filter(|c| c.is_alphanumeric())
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        })
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,423 @@
+//! Machine-readable speech start/end events, one JSON object per line, so
+//! downstream tools can line up with the audio timeline. With `--format
+//! jsonl` they go to stdout along with partial and final transcripts, and
+//! the human-readable output moves to stderr.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fmt;
+use std::fs::File;
+use std::io::{IsTerminal, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, AtomicI8, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{broadcast, mpsc};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+use uuid::Uuid;
+
+use crate::connection::Word;
+use crate::webhook::Webhook;
+use crate::{mqtt, overlay, rebroadcast, Args};
+
+/// What goes to stdout.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per event
+    Jsonl,
+}
+
+/// Set under `--format jsonl`, when `say!` writes to stderr
+static ON_STDERR: AtomicBool = AtomicBool::new(false);
+
+pub fn set_format(format: Format) {
+    ON_STDERR.store(format == Format::Jsonl, Ordering::Relaxed);
+}
+
+/// Cleared by --no-color and NO_COLOR
+static COLOR: AtomicBool = AtomicBool::new(true);
+
+pub fn set_color(enabled: bool) {
+    COLOR.store(enabled, Ordering::Relaxed);
+}
+
+/// -1 under --quiet, otherwise the number of -v
+static VERBOSITY: AtomicI8 = AtomicI8::new(0);
+
+pub fn set_verbosity(quiet: bool, verbose: u8) {
+    let level = if quiet { -1 } else { verbose.min(2) as i8 };
+    VERBOSITY.store(level, Ordering::Relaxed);
+}
+
+/// Set while a UI shows the human-readable output
+static LINES: OnceLock<mpsc::UnboundedSender<String>> = OnceLock::new();
+
+/// Hand everything `say!` prints to a UI instead of the terminal.
+#[cfg(feature = "tui")]
+pub fn capture_lines() -> mpsc::UnboundedReceiver<String> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let _ = LINES.set(tx);
+    rx
+}
+
+/// Cleared by --meter, which draws its own line in place
+static LIVE_PARTIALS: AtomicBool = AtomicBool::new(true);
+/// Set while the last output is a partial drawn in place, which the next
+/// output replaces
+static LIVE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_live_partials(enabled: bool) {
+    LIVE_PARTIALS.store(enabled, Ordering::Relaxed);
+}
+
+/// Behind `say!`. Once the UI is gone its lines go to the terminal again.
+pub fn say(text: fmt::Arguments) {
+    if VERBOSITY.load(Ordering::Relaxed) < 0 {
+        return;
+    }
+    if LINES
+        .get()
+        .is_some_and(|lines| lines.send(text.to_string()).is_ok())
+    {
+        return;
+    }
+    print(&text.to_string(), false);
+}
+
+/// Write where `say!` goes, over a partial drawn in place. A `live` line
+/// stays open for the next output to replace.
+fn print(text: &str, live: bool) {
+    let clear = if LIVE.swap(live, Ordering::Relaxed) {
+        "\r\x1b[K"
+    } else {
+        ""
+    };
+    let end = if live { "" } else { "\n" };
+    // A closed pipe downstream shouldn't stop transcription
+    if ON_STDERR.load(Ordering::Relaxed) {
+        let mut out = std::io::stderr().lock();
+        let _ = write!(out, "{}{}{}", clear, text, end).and_then(|_| out.flush());
+    } else {
+        let mut out = std::io::stdout().lock();
+        let _ = write!(out, "{}{}{}", clear, text, end).and_then(|_| out.flush());
+    }
+}
+
+/// Behind the partial line. On a terminal each partial is redrawn in place
+/// of the last, keeping only as much of the end of `text` as fits the
+/// width: a partial that wrapped couldn't be cleared with the line. `paint`
+/// styles the part shown.
+pub fn say_partial(prefix: &str, text: &str, paint: impl FnOnce(&str) -> String) {
+    if VERBOSITY.load(Ordering::Relaxed) < 0 {
+        return;
+    }
+    let Some(columns) = live_columns() else {
+        say(format_args!("{}{}", prefix, paint(text)));
+        return;
+    };
+    // A spare column, so the cursor never wraps either
+    let room = columns.saturating_sub(visible_width(prefix) + 1);
+    let (shown, cut) = tail(text, room);
+    let ellipsis = if cut { "…" } else { "" };
+    print(&format!("{}{}{}", prefix, ellipsis, paint(shown)), true);
+}
+
+/// Width of the terminal `say!` writes to, if partials are drawn in place.
+fn live_columns() -> Option<usize> {
+    if !LIVE_PARTIALS.load(Ordering::Relaxed) || LINES.get().is_some() {
+        return None;
+    }
+    let on_stderr = ON_STDERR.load(Ordering::Relaxed);
+    let terminal = if on_stderr {
+        std::io::stderr().is_terminal()
+    } else {
+        std::io::stdout().is_terminal()
+    };
+    if !terminal {
+        return None;
+    }
+    #[cfg(unix)]
+    {
+        let fd = if on_stderr {
+            libc::STDERR_FILENO
+        } else {
+            libc::STDOUT_FILENO
+        };
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } == 0 && size.ws_col > 0 {
+            return Some(size.ws_col as usize);
+        }
+    }
+    std::env::var("COLUMNS").ok()?.parse().ok()
+}
+
+/// The end of `text` that fits in `room` columns, from a word boundary if
+/// possible, and whether anything was cut for an ellipsis.
+fn tail(text: &str, room: usize) -> (&str, bool) {
+    if text.width() <= room {
+        return (text, false);
+    }
+    let Some(room) = room.checked_sub(1).filter(|&room| room > 0) else {
+        return ("", true);
+    };
+    let fits = |start: usize| text[start..].width() <= room;
+    let start = text
+        .match_indices(' ')
+        .map(|(i, _)| i + 1)
+        .find(|&i| fits(i))
+        .unwrap_or_else(|| {
+            // One word too long for the line
+            let mut width = 0;
+            text.char_indices()
+                .rev()
+                .take_while(|(_, c)| {
+                    width += c.width().unwrap_or(0);
+                    width <= room
+                })
+                .last()
+                .map_or(text.len(), |(i, _)| i)
+        });
+    (&text[start..], true)
+}
+
+/// Columns `text` takes up, not counting ANSI escape sequences.
+fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut escape = false;
+    for c in text.chars() {
+        match c {
+            '\x1b' => escape = true,
+            c if escape => escape = !c.is_ascii_alphabetic(),
+            c => width += c.width().unwrap_or(0),
+        }
+    }
+    width
+}
+
+/// Behind `say_final!`: under --quiet only the transcript itself, unless
+/// stdout is carrying JSON.
+pub fn say_final(transcript: &str, line: fmt::Arguments) {
+    if VERBOSITY.load(Ordering::Relaxed) >= 0 {
+        say(line);
+    } else if !ON_STDERR.load(Ordering::Relaxed) {
+        println!("{}", transcript);
+    }
+}
+
+/// Behind `verbose!` and `trace!`: diagnostics on stderr at -v and -vv, so
+/// they never mix with transcripts piped from stdout.
+pub fn detail(level: i8, text: fmt::Arguments) {
+    if VERBOSITY.load(Ordering::Relaxed) < level {
+        return;
+    }
+    if LINES
+        .get()
+        .is_some_and(|lines| lines.send(text.to_string()).is_ok())
+    {
+        return;
+    }
+    // Clear a partial drawn in place first
+    if LIVE.load(Ordering::Relaxed) {
+        print("", true);
+    }
+    eprintln!("{}", text);
+}
+
+/// Whether `say!` output reaches a terminal, where ANSI styling shows, and
+/// colors weren't turned off.
+pub fn styled() -> bool {
+    if !COLOR.load(Ordering::Relaxed) || LINES.get().is_some() {
+        return false;
+    }
+    if ON_STDERR.load(Ordering::Relaxed) {
+        std::io::stderr().is_terminal()
+    } else {
+        std::io::stdout().is_terminal()
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum VadEvent<'a> {
+    SpeechStart {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        device: Option<&'a str>,
+        utterance_id: Uuid,
+        /// Unix time in milliseconds
+        wall_ms: u64,
+        /// Position in the session's audio at the target sample rate
+        sample: u64,
+    },
+    SpeechEnd {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        device: Option<&'a str>,
+        utterance_id: Uuid,
+        wall_ms: u64,
+        sample: u64,
+        duration_ms: u32,
+        /// silence, max_length, stable_partial, talk_key or discarded
+        reason: &'static str,
+    },
+    /// The utterance starting at `sample` sounds like a different speaker
+    SpeakerChange {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        device: Option<&'a str>,
+        utterance_id: Uuid,
+        wall_ms: u64,
+        sample: u64,
+        pitch_hz: f32,
+    },
+    /// Interim transcript of the utterance so far (--format jsonl)
+    Partial {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        device: Option<&'a str>,
+        utterance_id: Uuid,
+        wall_ms: u64,
+        /// Where the utterance starts
+        sample: u64,
+        text: &'a str,
+        /// From a diarizing server
+        #[serde(skip_serializing_if = "Option::is_none")]
+        speaker: Option<&'a str>,
+    },
+    /// Transcript of a whole utterance (--format jsonl)
+    Final {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        device: Option<&'a str>,
+        /// Of the connection, kept across reconnects
+        session_id: Uuid,
+        utterance_id: Uuid,
+        wall_ms: u64,
+        /// Span of the utterance; unknown for speech replayed after an outage
+        #[serde(skip_serializing_if = "Option::is_none")]
+        start_sample: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        end_sample: Option<u64>,
+        text: &'a str,
+        /// From the end of speech to the transcript
+        #[serde(skip_serializing_if = "Option::is_none")]
+        e2e_ms: Option<f64>,
+        /// Server round trip
+        #[serde(skip_serializing_if = "Option::is_none")]
+        rtt_ms: Option<f64>,
+        /// With --word-timestamps; seconds from the start of the utterance
+        #[serde(skip_serializing_if = "<[_]>::is_empty")]
+        words: &'a [Word],
+        /// Of the whole transcript, 0 to 1
+        #[serde(skip_serializing_if = "Option::is_none")]
+        confidence: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        speaker: Option<&'a str>,
+    },
+}
+
+impl VadEvent<'_> {
+    /// The `event` field
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::SpeechStart { .. } => "speech_start",
+            Self::SpeechEnd { .. } => "speech_end",
+            Self::SpeakerChange { .. } => "speaker_change",
+            Self::Partial { .. } => "partial",
+            Self::Final { .. } => "final",
+        }
+    }
+}
+
+/// Shared event writer; sessions for several devices write to the same output.
+#[derive(Clone)]
+pub struct Events {
+    out: Option<Arc<Mutex<Box<dyn Write + Send>>>>,
+    /// Whether partials and finals are written too
+    transcripts: bool,
+    /// Caption overlay pages and rebroadcast subscribers following along
+    listeners: Option<broadcast::Sender<String>>,
+    mqtt: Option<mqtt::Publisher>,
+    webhook: Option<Webhook>,
+}
+
+impl Events {
+    /// The sink for --vad-events, or stdout under `--format jsonl`, plus the
+    /// caption overlay, WebSocket rebroadcast, MQTT and webhook.
+    pub fn for_args(args: &Args) -> Result<Option<Self>> {
+        let (out, transcripts) = match (&args.vad_events, args.format) {
+            (_, Format::Jsonl) => (Some(open("-")?), true),
+            (Some(dest), Format::Text) => (Some(open(dest)?), false),
+            (None, Format::Text) => (None, false),
+        };
+        let listeners = (args.http_port.is_some() || args.ws_broadcast.is_some())
+            .then(|| broadcast::channel(256).0);
+        if let (Some(port), Some(tx)) = (args.http_port, &listeners) {
+            overlay::serve(args, port, tx.clone())?;
+            say!("[overlay] Captions at http://127.0.0.1:{}/", port);
+        }
+        if let (Some(addr), Some(tx)) = (&args.ws_broadcast, &listeners) {
+            rebroadcast::serve(addr, tx.clone())?;
+            say!("[rebroadcast] Events at ws://{}/", addr);
+        }
+        let mqtt = mqtt::Publisher::for_args(args)?;
+        let webhook = Webhook::for_args(args)?;
+        if out.is_none() && listeners.is_none() && mqtt.is_none() && webhook.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            out: out.map(|out| Arc::new(Mutex::new(out))),
+            transcripts,
+            listeners,
+            mqtt,
+            webhook,
+        }))
+    }
+
+    pub fn emit(&self, event: &VadEvent) {
+        let Ok(line) = serde_json::to_string(event) else {
+            return;
+        };
+        if let Some(listeners) = &self.listeners {
+            // No page open yet is fine
+            let _ = listeners.send(line.clone());
+        }
+        if let Some(mqtt) = &self.mqtt {
+            mqtt.publish(event.name(), &line);
+        }
+        if let (Some(webhook), VadEvent::Final { .. }) = (&self.webhook, event) {
+            webhook.send(line.clone());
+        }
+        if !self.transcripts && matches!(event, VadEvent::Partial { .. } | VadEvent::Final { .. }) {
+            return;
+        }
+        if let Some(Ok(mut out)) = self.out.as_ref().map(|out| out.lock()) {
+            // A consumer going away shouldn't stop transcription
+            let _ = writeln!(out, "{}", line).and_then(|_| out.flush());
+        }
+    }
+
+    /// Give webhook deliveries still queued up to `wait` before exiting.
+    pub async fn flush(&self, wait: Duration) {
+        if let Some(webhook) = &self.webhook {
+            webhook.flush(wait).await;
+        }
+    }
+}
+
+/// Open `-` (stdout), `tcp:HOST:PORT`, or a file path.
+fn open(dest: &str) -> Result<Box<dyn Write + Send>> {
+    Ok(if dest == "-" {
+        Box::new(std::io::stdout())
+    } else if let Some(addr) = dest.strip_prefix("tcp:") {
+        Box::new(
+            TcpStream::connect(addr)
+                .with_context(|| format!("Cannot connect to event listener {}", addr))?,
+        )
+    } else {
+        Box::new(File::create(dest).with_context(|| format!("Cannot create event log {}", dest))?)
+    })
+}
+
+pub fn wall_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
@@ -0,0 +1,79 @@
+//! Per-chunk detector state dumped as CSV, so thresholds can be tuned
+//! offline against a recorded session.
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// What the speech detector saw and decided for one chunk.
+pub struct ChunkFeatures {
+    pub energy: f32,
+    pub min_energy: f32,
+    pub vad: bool,
+    pub loud: bool,
+    pub speech_like: bool,
+    pub speech: bool,
+    pub speaking: bool,
+    pub onset_count: u32,
+    pub silence_count: u32,
+    pub finalize: bool,
+}
+
+pub struct FeatureDump {
+    out: BufWriter<File>,
+    chunk_ms: u32,
+    chunks: u64,
+}
+
+impl FeatureDump {
+    /// Create the CSV; sessions with a device label get their own file
+    /// (`features.csv` becomes `features-<label>.csv`).
+    pub fn create(path: &Path, label: Option<&str>, chunk_ms: u32) -> Result<Self> {
+        let path = match label {
+            Some(label) => labelled_path(path, label),
+            None => path.to_path_buf(),
+        };
+        let file = File::create(&path)
+            .with_context(|| format!("Cannot create feature dump {}", path.display()))?;
+        let mut out = BufWriter::new(file);
+        writeln!(
+            out,
+            "time_ms,energy,min_energy,vad,loud,speech_like,speech,speaking,onset_count,silence_count,finalize"
+        )?;
+        Ok(Self {
+            out,
+            chunk_ms,
+            chunks: 0,
+        })
+    }
+
+    pub fn write(&mut self, f: &ChunkFeatures) -> Result<()> {
+        writeln!(
+            self.out,
+            "{},{:.6},{:.6},{},{},{},{},{},{},{},{}",
+            self.chunks * self.chunk_ms as u64,
+            f.energy,
+            f.min_energy,
+            f.vad as u8,
+            f.loud as u8,
+            f.speech_like as u8,
+            f.speech as u8,
+            f.speaking as u8,
+            f.onset_count,
+            f.silence_count,
+            f.finalize as u8
+        )?;
+        self.chunks += 1;
+        Ok(())
+    }
+}
+
+fn labelled_path(path: &Path, label: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, label, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, label),
+    };
+    path.with_file_name(name)
+}
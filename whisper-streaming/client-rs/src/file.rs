@@ -0,0 +1,212 @@
+//! Transcribe a recording instead of live input.
+
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::connection::{Connection, Session};
+use crate::frontend::Frontend;
+use crate::speaker::SpeakerLabels;
+use crate::subtitles::Subtitles;
+use crate::transcript::TranscriptLog;
+use crate::{
+    dsp, events, paint_final, run_session, short_id, strip_overlap, Args, FileArgs, SessionInput,
+};
+
+pub async fn run(args: &Args, opts: &FileArgs) -> Result<()> {
+    let (samples, sample_rate) = load_wav(&opts.path)?;
+    say!(
+        "[file] {} ({:.1}s at {}Hz)",
+        opts.path.display(),
+        samples.len() as f64 / sample_rate as f64,
+        sample_rate
+    );
+    if opts.windowed {
+        run_windowed(args, opts, &samples, sample_rate).await
+    } else {
+        run_segmented(args, samples, sample_rate).await
+    }
+}
+
+/// Feed the file through the usual speech detection, as if it were a device.
+async fn run_segmented(args: &Args, samples: Vec<f32>, sample_rate: u32) -> Result<()> {
+    let (audio_tx, audio_rx) = mpsc::channel(100);
+    // Trailing silence lets an utterance running to the end of the file finish
+    let silence_ms =
+        args.silence_threshold_ms.max(args.max_silence_ms) + args.merge_gap_ms + 2 * args.chunk_ms;
+    let padding = vec![0.0; (sample_rate as u64 * silence_ms as u64 / 1000) as usize];
+    let block = (sample_rate / 100).max(1) as usize;
+    tokio::spawn(async move {
+        for chunk in samples.chunks(block).chain(padding.chunks(block)) {
+            if audio_tx.send(chunk.to_vec()).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let events = events::Events::for_args(args)?;
+    let input = SessionInput {
+        label: None,
+        audio_rx,
+        device_sample_rate: sample_rate,
+        device_name: None,
+        echo_ref: None,
+        meter_tx: None,
+        status_tx: None,
+        talk: None,
+        barge_in: None,
+        events: events.clone(),
+        subtitles: Subtitles::for_args(args)?,
+        transcript_log: args
+            .output
+            .as_deref()
+            .map(TranscriptLog::open)
+            .transpose()?,
+        dictation: None,
+        mute: None,
+    };
+    let report = run_session(args, input, std::future::pending()).await?;
+    if let Some(events) = &events {
+        events
+            .flush(Duration::from_millis(args.shutdown_timeout_ms))
+            .await;
+    }
+    say!("\n--- Segmentation Summary ---");
+    say!("{}", report.segments.summary());
+    Ok(())
+}
+
+/// Send the whole file in fixed overlapping windows, ignoring its pauses.
+async fn run_windowed(
+    args: &Args,
+    opts: &FileArgs,
+    samples: &[f32],
+    sample_rate: u32,
+) -> Result<()> {
+    if opts.overlap_secs < 0.0 || opts.overlap_secs >= opts.window_secs {
+        bail!("--overlap-secs must be less than --window-secs");
+    }
+
+    // Same preprocessing as live input; a block of silence flushes the last
+    // partial chunk through the pipeline
+    let mut frontend = Frontend::new(args, sample_rate, args.chunk_ms, None)?;
+    let flush = vec![0.0; (sample_rate * args.chunk_ms / 1000) as usize];
+    let mut audio: Vec<f32> = frontend.push(samples)?.concat();
+    audio.extend(frontend.push(&flush)?.concat());
+    let rate = args.sample_rate;
+    audio.truncate((samples.len() as u64 * rate as u64 / sample_rate as u64) as usize);
+
+    let window = (opts.window_secs * rate as f32) as usize;
+    let step = window - (opts.overlap_secs * rate as f32) as usize;
+    let mut conn = Connection::open(args)
+        .await
+        .with_context(|| format!("Cannot connect to {}", args.server_urls.join(", ")))?;
+    let session = Session::new(None);
+    conn.resume(&session).await?;
+    conn.configure(args, None).await?;
+    let events = events::Events::for_args(args)?;
+    let subtitles = Subtitles::for_args(args)?;
+    let transcript_log = args
+        .output
+        .as_deref()
+        .map(TranscriptLog::open)
+        .transpose()?;
+    let at = |sample: usize| Duration::from_millis(sample as u64 * 1000 / rate as u64);
+
+    let mut speaker_labels = SpeakerLabels::default();
+    let mut previous: Option<String> = None;
+    let mut start = 0;
+    while start < audio.len() {
+        let end = (start + window).min(audio.len());
+        let mut chunk = audio[start..end].to_vec();
+        if let Some(target) = args.normalize_lufs {
+            dsp::normalize_loudness(&mut chunk, rate, target);
+        }
+        let id = Uuid::new_v4();
+        let span = format!(
+            "{:.1}-{:.1}s id:{}",
+            start as f64 / rate as f64,
+            end as f64 / rate as f64,
+            short_id(id)
+        );
+        match conn.transcribe(&chunk, rate, id).await? {
+            Some(resp) if resp.msg_type == "noise" => {
+                previous = None;
+                say!("[noise {}] {}", span, resp.sample.unwrap_or_default());
+            }
+            Some(resp) => {
+                let mut text = resp.text.unwrap_or_default().trim().to_string();
+                let mut words = resp.words;
+                if let Some(prev) = previous.take() {
+                    text = strip_overlap(&prev, &text);
+                    // The timings would still include the overlap
+                    words.clear();
+                }
+                if !text.is_empty() {
+                    let shown = paint_final(&text, &words, resp.confidence, args.low_confidence);
+                    let speaker = speaker_labels.prefix(resp.speaker.as_deref(), events::styled());
+                    say_final!(&text, "{}[{}] {}", speaker, span, shown);
+                    if let Some(events) = &events {
+                        events.emit(&events::VadEvent::Final {
+                            device: None,
+                            session_id: session.id,
+                            utterance_id: id,
+                            wall_ms: events::wall_ms(),
+                            start_sample: Some(start as u64),
+                            end_sample: Some(end as u64),
+                            text: &text,
+                            e2e_ms: None,
+                            rtt_ms: None,
+                            words: &words,
+                            confidence: resp.confidence,
+                            speaker: resp.speaker.as_deref(),
+                        });
+                    }
+                    if let Some(subtitles) = &subtitles {
+                        subtitles.cue(at(start), at(end), None, &text, &words);
+                    }
+                    if let Some(log) = &transcript_log {
+                        log.write(None, &text);
+                    }
+                }
+                previous = Some(text);
+            }
+            None => {}
+        }
+        if end == audio.len() {
+            break;
+        }
+        start += step;
+    }
+    if let Some(events) = &events {
+        events
+            .flush(Duration::from_millis(args.shutdown_timeout_ms))
+            .await;
+    }
+    Ok(())
+}
+
+/// Read a WAV file as mono samples, averaging channels.
+pub fn load_wav(path: &Path) -> Result<(Vec<f32>, u32)> {
+    let mut reader =
+        hound::WavReader::open(path).with_context(|| format!("Cannot open {}", path.display()))?;
+    let spec = reader.spec();
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+    let channels = spec.channels as usize;
+    let mono = interleaved
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+    Ok((mono, spec.sample_rate))
+}
@@ -0,0 +1,175 @@
+use anyhow::Result;
+
+use crate::config::StageConfig;
+use crate::dsp::{self, EchoReference};
+use crate::Args;
+
+pub const VOICE_BAND_HZ: (f32, f32) = (300.0, 3400.0);
+
+/// Turns raw device audio into fixed-size, preprocessed chunks at the target
+/// sample rate.
+pub struct Frontend {
+    resampler: dsp::Resampler,
+    dsp: dsp::Pipeline,
+    device_chunk_size: usize,
+    chunk_size: usize,
+    audio_buffer: Vec<f32>,
+    resampled: Vec<f32>,
+}
+
+impl Frontend {
+    pub fn new(
+        args: &Args,
+        device_sample_rate: u32,
+        chunk_ms: u32,
+        echo_ref: Option<EchoReference>,
+    ) -> Result<Self> {
+        let device_chunk_size = (device_sample_rate * chunk_ms / 1000) as usize;
+        let chunk_size = (args.sample_rate * chunk_ms / 1000) as usize;
+        let resampler = dsp::Resampler::new(
+            args.resampler,
+            args.resampler_quality,
+            device_sample_rate,
+            args.sample_rate,
+            device_chunk_size,
+        )?;
+
+        let mut dsp = dsp::Pipeline::default();
+        for stage in stage_chain(args) {
+            dsp.push_boxed(build_stage(&stage, args.sample_rate, echo_ref.as_ref())?);
+        }
+
+        Ok(Self {
+            resampler,
+            dsp,
+            device_chunk_size,
+            chunk_size,
+            audio_buffer: Vec::with_capacity(device_chunk_size * 2),
+            resampled: Vec::with_capacity(chunk_size * 2),
+        })
+    }
+
+    /// Buffer newly captured samples and return every chunk now complete.
+    pub fn push(&mut self, samples: &[f32]) -> Result<Vec<Vec<f32>>> {
+        self.audio_buffer.extend_from_slice(samples);
+
+        // Collect complete chunks at device sample rate
+        while self.audio_buffer.len() >= self.device_chunk_size {
+            let device_chunk: Vec<f32> =
+                self.audio_buffer.drain(..self.device_chunk_size).collect();
+
+            // Resample to target rate for VAD
+            self.resampled
+                .extend(self.resampler.process(&device_chunk)?);
+        }
+
+        // Process complete chunks at target sample rate
+        let mut chunks = Vec::new();
+        while self.resampled.len() >= self.chunk_size {
+            let mut chunk: Vec<f32> = self.resampled.drain(..self.chunk_size).collect();
+            self.dsp.process(&mut chunk);
+            chunks.push(chunk);
+        }
+        Ok(chunks)
+    }
+}
+
+/// The preprocessing chain in order: the `[[dsp]]` list from the config file
+/// if given, otherwise the stages enabled by flags.
+pub fn stage_chain(args: &Args) -> Vec<StageConfig> {
+    if let Some(stages) = &args.config.dsp {
+        return stages.clone();
+    }
+
+    let mut stages = Vec::new();
+    if args.aec {
+        stages.push(StageConfig::Aec {
+            tail_ms: args.aec_tail_ms,
+        });
+    }
+    if args.voice_band {
+        // Telephone band; supersedes the rumble high-pass
+        stages.push(StageConfig::VoiceBand);
+    } else if args.highpass_hz > 0.0 {
+        stages.push(StageConfig::Highpass {
+            cutoff_hz: args.highpass_hz,
+        });
+    }
+    if args.gain != 0.0 {
+        stages.push(StageConfig::Gain { db: args.gain });
+    }
+    if args.dereverb {
+        stages.push(StageConfig::Dereverb {
+            rt60_ms: args.dereverb_rt60_ms,
+        });
+    }
+    if let Some(path) = &args.noise_profile {
+        stages.push(StageConfig::SpectralSubtraction {
+            profile: path.clone(),
+        });
+    }
+    if args.denoise {
+        stages.push(StageConfig::Denoise);
+    }
+    for path in &args.dsp_plugins {
+        stages.push(StageConfig::Plugin { path: path.clone() });
+    }
+    if args.agc {
+        stages.push(StageConfig::Agc {
+            target: args.agc_target,
+            floor: args.agc_floor,
+            max_gain_db: args.agc_max_gain,
+        });
+    }
+    // Boosted audio is limited before i16 conversion clamps it
+    if (args.gain > 0.0 || args.agc) && !args.no_limiter {
+        stages.push(StageConfig::Limiter {
+            threshold: args.limiter_threshold,
+        });
+    }
+    stages
+}
+
+/// Instantiate a configured stage. AEC needs the reference of the client's
+/// own playback.
+pub fn build_stage(
+    stage: &StageConfig,
+    sample_rate: u32,
+    echo_ref: Option<&EchoReference>,
+) -> Result<Box<dyn dsp::Stage>> {
+    let stage: Box<dyn dsp::Stage> = match stage {
+        StageConfig::Aec { tail_ms } => match echo_ref {
+            Some(echo_ref) => Box::new(dsp::EchoCanceller::new(echo_ref.clone(), *tail_ms)),
+            None => {
+                anyhow::bail!("The aec stage needs a playback reference, which only selftest has")
+            }
+        },
+        StageConfig::Highpass { cutoff_hz } => {
+            Box::new(dsp::Biquad::highpass(sample_rate, *cutoff_hz))
+        }
+        StageConfig::Lowpass { cutoff_hz } => {
+            Box::new(dsp::Biquad::lowpass(sample_rate, *cutoff_hz))
+        }
+        StageConfig::VoiceBand => {
+            let mut band = dsp::Pipeline::default();
+            band.push(dsp::Biquad::highpass(sample_rate, VOICE_BAND_HZ.0));
+            band.push(dsp::Biquad::lowpass(sample_rate, VOICE_BAND_HZ.1));
+            Box::new(band)
+        }
+        StageConfig::Gain { db } => Box::new(dsp::Gain::new(*db)),
+        StageConfig::Dereverb { rt60_ms } => Box::new(dsp::Dereverb::new(sample_rate, *rt60_ms)),
+        StageConfig::SpectralSubtraction { profile } => {
+            let profile = dsp::NoiseProfile::load(profile)?;
+            Box::new(dsp::SpectralSubtraction::new(profile, sample_rate)?)
+        }
+        StageConfig::Denoise => Box::new(dsp::Denoise::new(sample_rate)?),
+        StageConfig::Agc {
+            target,
+            floor,
+            max_gain_db,
+        } => Box::new(dsp::Agc::new(sample_rate, *target, *floor, *max_gain_db)),
+        StageConfig::Limiter { threshold } => Box::new(dsp::SoftLimiter::new(*threshold)),
+        StageConfig::Plugin { path } => Box::new(dsp::Plugin::load(path, sample_rate)?),
+    };
+    Ok(stage)
+}
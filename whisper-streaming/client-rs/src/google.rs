@@ -0,0 +1,239 @@
+//! Google Cloud Speech-to-Text v2 backend: one `StreamingRecognize` call per
+//! utterance, authenticated with an Application Default Credentials token.
+
+use anyhow::{bail, Context, Result};
+use std::time::{Duration, Instant};
+use tonic::codec::ProstCodec;
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::metadata::MetadataValue;
+use tonic::transport::{Channel, ClientTlsConfig};
+
+use crate::connection::ServerResponse;
+use crate::dsp;
+use crate::Args;
+
+const STREAMING_RECOGNIZE: &str = "/google.cloud.speech.v2.Speech/StreamingRecognize";
+/// 100ms of 16kHz LINEAR16, well under the per-message limit
+const CHUNK_BYTES: usize = 3200;
+/// ADC access tokens live an hour
+const TOKEN_LIFETIME: Duration = Duration::from_secs(45 * 60);
+
+/// The parts of `google/cloud/speech/v2/cloud_speech.proto` used here,
+/// written out by hand like `proto.rs`. Tags must match the schema.
+mod speech {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct StreamingRecognizeRequest {
+        #[prost(string, tag = "3")]
+        pub recognizer: String,
+        #[prost(oneof = "streaming_recognize_request::StreamingRequest", tags = "6, 5")]
+        pub streaming_request: Option<streaming_recognize_request::StreamingRequest>,
+    }
+
+    pub mod streaming_recognize_request {
+        #[derive(Clone, PartialEq, prost::Oneof)]
+        pub enum StreamingRequest {
+            #[prost(message, tag = "6")]
+            StreamingConfig(super::StreamingRecognitionConfig),
+            #[prost(bytes, tag = "5")]
+            Audio(Vec<u8>),
+        }
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct StreamingRecognitionConfig {
+        #[prost(message, optional, tag = "1")]
+        pub config: Option<RecognitionConfig>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct RecognitionConfig {
+        #[prost(message, optional, tag = "8")]
+        pub explicit_decoding_config: Option<ExplicitDecodingConfig>,
+        #[prost(string, tag = "9")]
+        pub model: String,
+        #[prost(string, repeated, tag = "10")]
+        pub language_codes: Vec<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ExplicitDecodingConfig {
+        /// `AudioEncoding`; 1 is LINEAR16
+        #[prost(int32, tag = "1")]
+        pub encoding: i32,
+        #[prost(int32, tag = "2")]
+        pub sample_rate_hertz: i32,
+        #[prost(int32, tag = "3")]
+        pub audio_channel_count: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct StreamingRecognizeResponse {
+        #[prost(message, repeated, tag = "6")]
+        pub results: Vec<StreamingRecognitionResult>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct StreamingRecognitionResult {
+        #[prost(message, repeated, tag = "1")]
+        pub alternatives: Vec<SpeechRecognitionAlternative>,
+        #[prost(bool, tag = "2")]
+        pub is_final: bool,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SpeechRecognitionAlternative {
+        #[prost(string, tag = "1")]
+        pub transcript: String,
+    }
+}
+
+use speech::streaming_recognize_request::StreamingRequest;
+
+pub struct GoogleStt {
+    client: tonic::client::Grpc<Channel>,
+    /// `projects/{project}/locations/{location}/recognizers/_`
+    recognizer: String,
+    project: String,
+    model: String,
+    language: String,
+    explicit_token: Option<String>,
+    token: Option<(String, Instant)>,
+}
+
+impl GoogleStt {
+    pub async fn open(args: &Args) -> Result<Self> {
+        let project = args
+            .google_project
+            .clone()
+            .context("--backend google needs --google-project or GOOGLE_CLOUD_PROJECT")?;
+        let endpoint = match args.google_location.as_str() {
+            "global" => "https://speech.googleapis.com".to_string(),
+            region => format!("https://{}-speech.googleapis.com", region),
+        };
+        let channel = Channel::from_shared(endpoint)?
+            .tls_config(ClientTlsConfig::new().with_native_roots())?
+            .connect()
+            .await?;
+        let mut stt = Self {
+            client: tonic::client::Grpc::new(channel),
+            recognizer: format!(
+                "projects/{}/locations/{}/recognizers/_",
+                project, args.google_location
+            ),
+            project,
+            model: args.google_model.clone(),
+            language: args.google_language.clone(),
+            explicit_token: args.token.clone(),
+            token: None,
+        };
+        // Fail at connect time rather than on the first utterance
+        stt.access_token().await?;
+        Ok(stt)
+    }
+
+    /// --token if given, else an ADC token from gcloud, refreshed before it
+    /// expires.
+    async fn access_token(&mut self) -> Result<String> {
+        if let Some(token) = &self.explicit_token {
+            return Ok(token.clone());
+        }
+        if let Some((token, fetched)) = &self.token {
+            if fetched.elapsed() < TOKEN_LIFETIME {
+                return Ok(token.clone());
+            }
+        }
+        let output = tokio::process::Command::new("gcloud")
+            .args(["auth", "application-default", "print-access-token"])
+            .output()
+            .await
+            .context("Cannot run gcloud for Application Default Credentials")?;
+        if !output.status.success() {
+            bail!(
+                "gcloud auth application-default print-access-token failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let token = String::from_utf8(output.stdout)?.trim().to_string();
+        self.token = Some((token.clone(), Instant::now()));
+        Ok(token)
+    }
+
+    /// Transcribe one utterance. An error means the call failed; a call
+    /// without final results gives an empty transcript.
+    pub async fn transcribe(
+        &mut self,
+        audio: &[f32],
+        sample_rate: u32,
+    ) -> Result<Option<ServerResponse>> {
+        let config = speech::StreamingRecognitionConfig {
+            config: Some(speech::RecognitionConfig {
+                explicit_decoding_config: Some(speech::ExplicitDecodingConfig {
+                    encoding: 1,
+                    sample_rate_hertz: sample_rate as i32,
+                    audio_channel_count: 1,
+                }),
+                model: self.model.clone(),
+                language_codes: vec![self.language.clone()],
+            }),
+        };
+        let pcm: Vec<u8> = dsp::f32_to_i16(audio, false)
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let requests: Vec<_> = std::iter::once(StreamingRequest::StreamingConfig(config))
+            .chain(
+                pcm.chunks(CHUNK_BYTES)
+                    .map(|chunk| StreamingRequest::Audio(chunk.to_vec())),
+            )
+            .enumerate()
+            .map(|(i, request)| speech::StreamingRecognizeRequest {
+                // Only the config message names the recognizer
+                recognizer: if i == 0 {
+                    self.recognizer.clone()
+                } else {
+                    String::new()
+                },
+                streaming_request: Some(request),
+            })
+            .collect();
+
+        let token = self.access_token().await?;
+        let mut request = tonic::Request::new(tokio_stream::iter(requests));
+        let metadata = request.metadata_mut();
+        metadata.insert("authorization", format!("Bearer {}", token).parse()?);
+        metadata.insert("x-goog-user-project", self.project.parse()?);
+        metadata.insert(
+            "x-goog-request-params",
+            MetadataValue::try_from(format!("recognizer={}", self.recognizer))?,
+        );
+
+        self.client.ready().await?;
+        let mut responses = self
+            .client
+            .streaming::<_, _, speech::StreamingRecognizeResponse, _>(
+                request,
+                PathAndQuery::from_static(STREAMING_RECOGNIZE),
+                ProstCodec::default(),
+            )
+            .await?
+            .into_inner();
+        let mut transcript = Vec::new();
+        while let Some(response) = responses.message().await? {
+            for result in response.results.into_iter().filter(|r| r.is_final) {
+                if let Some(best) = result.alternatives.into_iter().next() {
+                    transcript.push(best.transcript.trim().to_string());
+                }
+            }
+        }
+        Ok(Some(ServerResponse {
+            msg_type: "result".to_string(),
+            text: Some(transcript.join(" ")),
+            sample: None,
+            message: None,
+            utterance_id: None,
+            words: Vec::new(),
+            confidence: None,
+            speaker: None,
+        }))
+    }
+}
@@ -0,0 +1,84 @@
+//! gRPC transport: the protobuf messages over a bidirectional
+//! `whisper.v1.Transcriber/StreamingRecognize` call.
+
+use anyhow::{Context, Result};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::codec::{ProstCodec, Streaming};
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::metadata::MetadataKey;
+use tonic::transport::Channel;
+
+use crate::proto;
+
+const STREAMING_RECOGNIZE: &str = "/whisper.v1.Transcriber/StreamingRecognize";
+
+pub struct GrpcStream {
+    client: tonic::client::Grpc<Channel>,
+    tx: mpsc::Sender<proto::ClientMessage>,
+    /// Request stream until the call starts
+    pending: Option<ReceiverStream<proto::ClientMessage>>,
+    replies: Option<Streaming<proto::ServerMessage>>,
+    /// Sent as metadata with the call
+    headers: Vec<(String, String)>,
+}
+
+impl GrpcStream {
+    /// Connect to `url` (e.g. `http://host:50051`). `keepalive` is the HTTP/2
+    /// ping interval and timeout.
+    pub async fn open(
+        url: &str,
+        headers: Vec<(String, String)>,
+        keepalive: Option<(Duration, Duration)>,
+    ) -> Result<Self> {
+        let mut endpoint = Channel::from_shared(url.to_string())?;
+        if let Some((interval, timeout)) = keepalive {
+            endpoint = endpoint
+                .http2_keep_alive_interval(interval)
+                .keep_alive_timeout(timeout)
+                .keep_alive_while_idle(true);
+        }
+        let channel = endpoint.connect().await?;
+        let (tx, rx) = mpsc::channel(16);
+        Ok(Self {
+            client: tonic::client::Grpc::new(channel),
+            tx,
+            pending: Some(ReceiverStream::new(rx)),
+            replies: None,
+            headers,
+        })
+    }
+
+    pub async fn send(&mut self, message: proto::ClientMessage) -> Result<()> {
+        self.tx.send(message).await.context("gRPC stream closed")
+    }
+
+    /// The next server message; `None` once the server ends the call.
+    pub async fn next(&mut self) -> Result<Option<proto::ServerMessage>> {
+        // The call starts on the first read, with requests already queued:
+        // some servers hold the response headers until the first message
+        if let Some(requests) = self.pending.take() {
+            let mut request = tonic::Request::new(requests);
+            for (name, value) in &self.headers {
+                request
+                    .metadata_mut()
+                    .append(MetadataKey::from_bytes(name.as_bytes())?, value.parse()?);
+            }
+            self.client.ready().await?;
+            let response = self
+                .client
+                .streaming(
+                    request,
+                    PathAndQuery::from_static(STREAMING_RECOGNIZE),
+                    ProstCodec::default(),
+                )
+                .await?;
+            self.replies = Some(response.into_inner());
+        }
+        match self.replies.as_mut() {
+            Some(replies) => Ok(replies.message().await?),
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Talk key handling for push-to-talk and toggle-to-talk. Reads the
+//! controlling terminal, so the client's terminal must have focus.
+
+use anyhow::Result;
+
+/// Parse a `--talk-key` value: a single character, or `space`.
+pub fn parse_key(s: &str) -> Result<char, String> {
+    let mut chars = s.chars();
+    match (s, chars.next(), chars.next()) {
+        ("space", _, _) => Ok(' '),
+        (_, Some(c), None) if c.is_ascii_graphic() => Ok(c),
+        _ => Err(format!(
+            "invalid key '{}' (expected one character or 'space')",
+            s
+        )),
+    }
+}
+
+pub fn key_name(key: char) -> String {
+    match key {
+        ' ' => "SPACE".to_string(),
+        c => c.to_uppercase().to_string(),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TalkMode {
+    /// Talking while the key is held
+    Hold,
+    /// Each press starts or ends talking
+    Toggle,
+}
+
+#[cfg(unix)]
+pub use unix::talk_key;
+
+#[cfg(not(unix))]
+pub struct TerminalGuard;
+
+#[cfg(not(unix))]
+pub fn talk_key(
+    _key: char,
+    _mode: TalkMode,
+) -> Result<(TerminalGuard, tokio::sync::watch::Receiver<bool>)> {
+    anyhow::bail!("The talk key needs a Unix terminal")
+}
+
+#[cfg(unix)]
+mod unix {
+    use anyhow::{bail, Result};
+    use std::time::{Duration, Instant};
+    use tokio::sync::watch;
+
+    use super::TalkMode;
+
+    /// Terminals only report key presses; a held key shows up as auto-repeat.
+    /// Until repeat starts the key counts as held for the typical repeat delay,
+    /// after that it's released once repeats stop arriving.
+    const REPEAT_DELAY: Duration = Duration::from_millis(700);
+    const REPEAT_GAP: Duration = Duration::from_millis(150);
+
+    /// Restores the terminal's line editing and echo on drop.
+    pub struct TerminalGuard {
+        original: libc::termios,
+    }
+
+    impl Drop for TerminalGuard {
+        fn drop(&mut self) {
+            // SAFETY: restoring settings previously read from the same fd
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+        }
+    }
+
+    /// Track whether the user is talking according to `key`. Key input is
+    /// read unbuffered and without echo, while output and Ctrl+C keep working
+    /// normally.
+    pub fn talk_key(key: char, mode: TalkMode) -> Result<(TerminalGuard, watch::Receiver<bool>)> {
+        // SAFETY: termios is plain data, filled in by tcgetattr
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            bail!("The talk key needs an interactive terminal on stdin");
+        }
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        // SAFETY: applying a modified copy of the current settings
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            bail!("Cannot configure the terminal for the talk key");
+        }
+        let guard = TerminalGuard { original };
+
+        let (tx, rx) = watch::channel(false);
+        std::thread::spawn(move || watch_key(key as u8, mode, tx));
+        Ok((guard, rx))
+    }
+
+    fn watch_key(key: u8, mode: TalkMode, talking: watch::Sender<bool>) {
+        let mut down = false;
+        let mut repeating = false;
+        let mut last_seen = Instant::now();
+        loop {
+            let timeout = if down {
+                let window = if repeating { REPEAT_GAP } else { REPEAT_DELAY };
+                window.saturating_sub(last_seen.elapsed()).as_millis() as i32
+            } else {
+                -1
+            };
+            let mut fd = libc::pollfd {
+                fd: libc::STDIN_FILENO,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // SAFETY: polling a single valid pollfd
+            let ready = unsafe { libc::poll(&mut fd, 1, timeout) };
+            if ready < 0 {
+                return;
+            }
+            if ready == 0 {
+                down = false;
+                repeating = false;
+                if mode == TalkMode::Hold && talking.send(false).is_err() {
+                    return;
+                }
+                continue;
+            }
+
+            let mut buf = [0u8; 64];
+            // SAFETY: reading into a local buffer of the given length
+            let n = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len()) };
+            if n <= 0 {
+                return;
+            }
+            if buf[..n as usize].contains(&key) {
+                last_seen = Instant::now();
+                if down {
+                    repeating = true;
+                } else {
+                    down = true;
+                    let next = match mode {
+                        TalkMode::Hold => true,
+                        TalkMode::Toggle => !*talking.borrow(),
+                    };
+                    if talking.send(next).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! Just enough HTTP/1.1 for the local pages the client serves (--vtt
+//! http:ADDR, --http-port): one request per connection, closed after the
+//! response. Also binds the WebSocket rebroadcast listener.
+
+use anyhow::{Context, Result};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Largest request head read
+const MAX_HEAD: usize = 8192;
+
+/// Bind `addr` from synchronous code running on the runtime.
+pub fn listen(addr: &str) -> Result<TcpListener> {
+    let listener =
+        std::net::TcpListener::bind(addr).with_context(|| format!("Cannot listen on {}", addr))?;
+    listener.set_nonblocking(true)?;
+    Ok(TcpListener::from_std(listener)?)
+}
+
+/// Read a request head and return its path without the query, or `None`
+/// if the client went away first.
+pub async fn request_path(stream: &mut TcpStream) -> Option<String> {
+    let mut head = Vec::new();
+    let mut buf = [0; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < MAX_HEAD {
+        let n = stream.read(&mut buf).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let target = head.lines().next()?.split_whitespace().nth(1)?;
+    Some(target.split('?').next().unwrap_or(target).to_string())
+}
+
+/// Send a complete response, readable from any origin.
+pub async fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) {
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    );
+    let _ = stream.write_all(head.as_bytes()).await;
+    let _ = stream.write_all(body).await;
+    let _ = stream.shutdown().await;
+}
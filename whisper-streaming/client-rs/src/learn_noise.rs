@@ -0,0 +1,67 @@
+use anyhow::Result;
+
+use crate::capture::Capture;
+use crate::config::StageConfig;
+use crate::dsp::{self, NoiseProfile};
+use crate::frontend;
+use crate::{Args, LearnNoiseArgs};
+
+/// Record room tone and save its spectrum for `--noise-profile`.
+pub async fn run(args: &Args, opts: &LearnNoiseArgs) -> Result<()> {
+    say!(
+        "[learn-noise] Recording {}s of room tone - stay quiet...",
+        opts.seconds
+    );
+
+    let device = args.devices.first().map(String::as_str);
+    let (capture, mut audio_rx) = Capture::start(device, args.config.channel_weights(device))?;
+    let device_rate = capture.sample_rate;
+    let wanted = (device_rate * opts.seconds) as usize;
+    let mut recorded = Vec::with_capacity(wanted);
+    while recorded.len() < wanted {
+        match audio_rx.recv().await {
+            Some(samples) => recorded.extend(samples),
+            None => break,
+        }
+    }
+    capture.stop();
+    recorded.truncate(wanted);
+
+    // Measure the signal as the subtraction stage will see it
+    let mut samples = dsp::resample(&recorded, device_rate, args.sample_rate);
+    for stage in pre_subtraction_stages(frontend::stage_chain(args)) {
+        frontend::build_stage(&stage, args.sample_rate, None)?.process(&mut samples);
+    }
+
+    let profile = NoiseProfile::learn(&samples, args.sample_rate)?;
+    profile.save(&opts.output)?;
+    say!(
+        "[learn-noise] Noise level {:.4} RMS, profile saved to {}",
+        dsp::calculate_energy(&samples),
+        opts.output.display()
+    );
+    Ok(())
+}
+
+/// Stages ahead of spectral subtraction in the chain. Without one, the linear
+/// filter and gain stages that would precede it.
+fn pre_subtraction_stages(chain: Vec<StageConfig>) -> Vec<StageConfig> {
+    let subtraction = chain
+        .iter()
+        .position(|s| matches!(s, StageConfig::SpectralSubtraction { .. }));
+    match subtraction {
+        Some(i) => chain[..i].to_vec(),
+        None => chain
+            .into_iter()
+            .filter(|s| {
+                matches!(
+                    s,
+                    StageConfig::Highpass { .. }
+                        | StageConfig::Lowpass { .. }
+                        | StageConfig::VoiceBand
+                        | StageConfig::Gain { .. }
+                )
+            })
+            .collect(),
+    }
+}
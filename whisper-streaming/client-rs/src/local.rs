@@ -0,0 +1,69 @@
+//! In-process whisper.cpp backend: utterances are transcribed on this
+//! machine with a GGML model, no server involved.
+
+use anyhow::{Context, Result};
+use std::path::Path;
+use whisper_rs::{
+    FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters, WhisperState,
+};
+
+use crate::connection::ServerResponse;
+use crate::dsp;
+
+/// whisper.cpp only takes 16kHz mono
+const SAMPLE_RATE: u32 = 16000;
+
+pub struct LocalWhisper {
+    state: WhisperState,
+    language: Option<String>,
+    /// Last transcript, the prompt for the next one as on the server
+    previous: String,
+}
+
+impl LocalWhisper {
+    pub fn open(model: &Path, language: Option<String>) -> Result<Self> {
+        let context = WhisperContext::new_with_params(model, WhisperContextParameters::default())
+            .with_context(|| format!("Cannot load whisper model {}", model.display()))?;
+        Ok(Self {
+            state: context.create_state()?,
+            language,
+            previous: String::new(),
+        })
+    }
+
+    /// Transcribe one utterance, blocking this worker thread while the model
+    /// runs. Failures give `None`: there is no connection to lose.
+    pub fn transcribe(&mut self, audio: &[f32], sample_rate: u32) -> Option<ServerResponse> {
+        let audio = dsp::resample(audio, sample_rate, SAMPLE_RATE);
+        let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+        params.set_language(self.language.as_deref());
+        if !self.previous.is_empty() {
+            params.set_initial_prompt(&self.previous);
+        }
+        params.set_print_progress(false);
+        params.set_print_realtime(false);
+        params.set_print_special(false);
+        params.set_print_timestamps(false);
+        tokio::task::block_in_place(|| self.state.full(params, &audio)).ok()?;
+
+        let text: String = self
+            .state
+            .as_iter()
+            .filter_map(|segment| segment.to_str_lossy().ok().map(|s| s.into_owned()))
+            .collect();
+        let text = text.trim().to_string();
+        if !text.is_empty() {
+            self.previous = text.clone();
+        }
+        Some(ServerResponse {
+            msg_type: "result".to_string(),
+            text: Some(text),
+            sample: None,
+            message: None,
+            utterance_id: None,
+            words: Vec::new(),
+            confidence: None,
+            speaker: None,
+        })
+    }
+}
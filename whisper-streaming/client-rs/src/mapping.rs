@@ -0,0 +1,58 @@
+//! Reading third-party server replies through the `[response]` field
+//! selectors of the config file.
+
+use serde_json::Value;
+
+use crate::config::ResponseConfig;
+use crate::connection::ServerResponse;
+
+/// The reply in `text` as selected by `mapping`, or `None` if it isn't JSON
+/// or has no type.
+pub fn map_reply(mapping: &ResponseConfig, text: &str) -> Option<ServerResponse> {
+    let value: Value = serde_json::from_str(text).ok()?;
+    let field = |selector: &Option<String>, default: &str| {
+        select(&value, selector.as_deref().unwrap_or(default))
+            .and_then(Value::as_str)
+            .map(str::to_string)
+    };
+    let msg_type = match field(&mapping.msg_type, "type") {
+        Some(name) => mapping.types.get(&name).cloned().unwrap_or(name),
+        None => {
+            let is_final = select(&value, mapping.is_final.as_deref()?)?.as_bool()?;
+            (if is_final { "result" } else { "partial" }).to_string()
+        }
+    };
+    Some(ServerResponse {
+        msg_type,
+        text: field(&mapping.text, "text"),
+        sample: field(&mapping.sample, "sample"),
+        message: field(&mapping.message, "message"),
+        utterance_id: field(&mapping.utterance_id, "utterance_id"),
+        words: Vec::new(),
+        confidence: None,
+        // IDs are often numbers
+        speaker: mapping
+            .speaker
+            .as_deref()
+            .and_then(|selector| select(&value, selector))
+            .and_then(|id| match id {
+                Value::String(id) => Some(id.clone()),
+                Value::Number(id) => Some(id.to_string()),
+                _ => None,
+            }),
+    })
+}
+
+/// The value at a JSONPath-style `selector` such as
+/// `$.results[0].alternatives[0].transcript`; the leading `$.` is optional.
+pub fn select<'a>(value: &'a Value, selector: &str) -> Option<&'a Value> {
+    let path = selector.strip_prefix('$').unwrap_or(selector);
+    let mut pointer = String::new();
+    for part in path.replace('[', ".").replace(']', "").split('.') {
+        if !part.is_empty() {
+            pointer.push('/');
+            pointer.push_str(&part.replace('~', "~0").replace('/', "~1"));
+        }
+    }
+    value.pointer(&pointer)
+}
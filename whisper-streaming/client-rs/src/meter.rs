@@ -0,0 +1,102 @@
+//! Per-chunk level metering shared with UIs.
+
+use std::io::Write;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Levels and detector state for the most recent chunk.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MeterReading {
+    pub rms: f32,
+    pub peak: f32,
+    /// Raw VAD decision
+    pub vad: bool,
+    /// Combined VAD/energy decision
+    pub speech: bool,
+    /// Whether an utterance is in progress
+    pub speaking: bool,
+    /// Consecutive speech chunks towards the onset threshold
+    pub onset_count: u32,
+    /// Energy threshold in effect
+    pub min_energy: f32,
+}
+
+pub fn channel() -> (watch::Sender<MeterReading>, watch::Receiver<MeterReading>) {
+    watch::channel(MeterReading::default())
+}
+
+/// Connection and transcript state of a session, for --tui.
+#[derive(Clone, Debug, Default)]
+pub struct Status {
+    pub connected: bool,
+    /// Utterances waiting in the offline buffer
+    pub queued: usize,
+    /// Interim transcript of the utterance in progress
+    pub partial: String,
+    pub last_e2e_ms: Option<f64>,
+    pub last_rtt_ms: Option<f64>,
+    pub finals: u32,
+    pub total_e2e_ms: f64,
+}
+
+/// Apply `update` to a session's status, if a UI is watching it.
+pub fn update_status(tx: &Option<watch::Sender<Status>>, update: impl FnOnce(&mut Status) -> bool) {
+    if let Some(tx) = tx {
+        tx.send_if_modified(update);
+    }
+}
+
+pub fn to_dbfs(level: f32) -> f32 {
+    20.0 * level.max(1e-6).log10()
+}
+
+/// Bar of `width` cells spanning -60..0 dBFS, with the threshold marked.
+fn bar(reading: &MeterReading, width: usize) -> String {
+    let cell =
+        |level: f32| (((to_dbfs(level) + 60.0) / 60.0).clamp(0.0, 1.0) * width as f32) as usize;
+    let filled = cell(reading.rms);
+    let threshold = cell(reading.min_energy);
+    (0..width)
+        .map(|i| match i {
+            _ if i == threshold => '|',
+            _ if i < filled => '#',
+            _ => '.',
+        })
+        .collect()
+}
+
+/// Render a live meter line on stderr for each labelled session until the
+/// task is aborted.
+pub async fn render(meters: Vec<(Option<String>, watch::Receiver<MeterReading>)>) {
+    let mut ticker = tokio::time::interval(Duration::from_millis(100));
+    loop {
+        ticker.tick().await;
+        let line: Vec<String> = meters
+            .iter()
+            .map(|(label, rx)| {
+                let reading = *rx.borrow();
+                let state = if reading.speaking {
+                    "SPEECH".to_string()
+                } else if reading.speech {
+                    format!("onset {}", reading.onset_count)
+                } else {
+                    String::new()
+                };
+                format!(
+                    "{}[{}] {:>4.0}dB pk {:>4.0}dB {} {}",
+                    label
+                        .as_deref()
+                        .map(|l| format!("{} ", l))
+                        .unwrap_or_default(),
+                    bar(&reading, 30),
+                    to_dbfs(reading.rms),
+                    to_dbfs(reading.peak),
+                    if reading.vad { "vad" } else { "   " },
+                    state
+                )
+            })
+            .collect();
+        eprint!("\r{}\x1b[K", line.join(" | "));
+        let _ = std::io::stderr().flush();
+    }
+}
@@ -0,0 +1,140 @@
+//! MQTT publishing (--mqtt): every transcript and speech event goes to a
+//! broker as the same JSON `--format jsonl` prints, on a topic per event
+//! type, for home-automation rules to react to. Publishing runs on its own
+//! task, which keeps reconnecting while the broker is away, so a missing
+//! broker never holds up transcription.
+
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use tokio::sync::mpsc;
+
+use crate::Args;
+
+/// Event types that can get their own topic
+const EVENTS: [&str; 5] = [
+    "speech_start",
+    "speech_end",
+    "speaker_change",
+    "partial",
+    "final",
+];
+
+/// Messages held while the broker is away
+#[cfg(feature = "mqtt")]
+const QUEUE: usize = 256;
+
+/// Queue of messages for the broker; clones share the one connection.
+#[derive(Clone)]
+pub struct Publisher {
+    tx: mpsc::Sender<(String, String)>,
+    /// `{event}` is replaced by the event type
+    template: String,
+    /// From --mqtt-event-topic, overriding the template
+    topics: HashMap<String, String>,
+}
+
+impl Publisher {
+    pub fn for_args(args: &Args) -> Result<Option<Self>> {
+        let Some(url) = &args.mqtt else {
+            return Ok(None);
+        };
+        let mut topics = HashMap::new();
+        for spec in &args.mqtt_event_topic {
+            let Some((event, topic)) = spec.split_once('=') else {
+                bail!("--mqtt-event-topic takes EVENT=TOPIC, got {:?}", spec);
+            };
+            if !EVENTS.contains(&event) {
+                bail!(
+                    "Unknown event {:?} in --mqtt-event-topic (expected one of {})",
+                    event,
+                    EVENTS.join(", ")
+                );
+            }
+            topics.insert(event.to_string(), topic.to_string());
+        }
+        Ok(Some(Self {
+            tx: connect(url)?,
+            template: args.mqtt_topic.clone(),
+            topics,
+        }))
+    }
+
+    /// Queue one event's JSON under its topic, dropping it if the broker
+    /// has been away long enough to fill the queue.
+    pub fn publish(&self, event: &str, payload: &str) {
+        let topic = match self.topics.get(event) {
+            Some(topic) => topic.clone(),
+            None => self.template.replace("{event}", event),
+        };
+        let _ = self.tx.try_send((topic, payload.to_string()));
+    }
+}
+
+/// Start the connection to `mqtt://[USER:PASS@]HOST[:PORT]`.
+#[cfg(feature = "mqtt")]
+fn connect(url: &str) -> Result<mpsc::Sender<(String, String)>> {
+    use anyhow::Context;
+    use rumqttc::{AsyncClient, MqttOptions, QoS};
+    use std::time::Duration;
+
+    use crate::theme;
+
+    let parsed = url::Url::parse(url).with_context(|| format!("Invalid --mqtt URL {}", url))?;
+    if parsed.scheme() != "mqtt" {
+        bail!("--mqtt takes mqtt://HOST[:PORT], got {}", url);
+    }
+    let Some(host) = parsed.host_str() else {
+        bail!("--mqtt URL {} has no host", url);
+    };
+    let id = format!(
+        "whisper-client-{}",
+        &uuid::Uuid::new_v4().simple().to_string()[..8]
+    );
+    let mut options = MqttOptions::new(id, host, parsed.port().unwrap_or(1883));
+    options.set_keep_alive(Duration::from_secs(30));
+    if !parsed.username().is_empty() {
+        options.set_credentials(parsed.username(), parsed.password().unwrap_or(""));
+    }
+    let (client, mut eventloop) = AsyncClient::new(options, 64);
+
+    let addr = format!("{}:{}", host, parsed.port().unwrap_or(1883));
+    tokio::spawn(async move {
+        // Report each outage once, not every retry
+        let mut failing = false;
+        loop {
+            match eventloop.poll().await {
+                Ok(rumqttc::Event::Incoming(rumqttc::Packet::ConnAck(_))) => {
+                    failing = false;
+                    say!("[mqtt] Connected to {}", addr);
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    if !std::mem::replace(&mut failing, true) {
+                        let warning = format!("[mqtt] Cannot reach {}: {}", addr, e);
+                        say!("{}", theme::paint(theme::Role::Warning, &warning));
+                    }
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    });
+
+    let (tx, mut rx) = mpsc::channel::<(String, String)>(QUEUE);
+    tokio::spawn(async move {
+        while let Some((topic, payload)) = rx.recv().await {
+            if client
+                .publish(topic, QoS::AtLeastOnce, false, payload)
+                .await
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+    Ok(tx)
+}
+
+#[cfg(not(feature = "mqtt"))]
+fn connect(_url: &str) -> Result<mpsc::Sender<(String, String)>> {
+    bail!("--mqtt needs a build with `--features mqtt`")
+}
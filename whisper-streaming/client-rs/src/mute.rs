@@ -0,0 +1,66 @@
+//! Hands-free mute: a spoken phrase silences the client until a resume
+//! phrase or key. Speech is still transcribed while muted, but only to listen
+//! for the resume phrase.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::endpoint::normalize;
+
+/// What a final transcript means for the mute state.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Heard {
+    /// Not muted and not a command: output as usual
+    Pass,
+    /// The mute phrase; the client is now muted
+    Mute,
+    /// The resume phrase; the client is listening again
+    Resume,
+    /// Muted: not output
+    Drop,
+}
+
+/// Mute state shared by all sessions.
+#[derive(Clone)]
+pub struct VoiceMute {
+    mute_phrase: String,
+    resume_phrase: String,
+    muted: Arc<AtomicBool>,
+}
+
+impl VoiceMute {
+    pub fn new(mute_phrase: &str, resume_phrase: &str) -> Self {
+        Self {
+            mute_phrase: normalize(mute_phrase),
+            resume_phrase: normalize(resume_phrase),
+            muted: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted.load(Ordering::Relaxed)
+    }
+
+    /// Unmute; true if the client was muted.
+    pub fn resume(&self) -> bool {
+        self.muted.swap(false, Ordering::Relaxed)
+    }
+
+    pub fn hear(&self, text: &str) -> Heard {
+        // Match whole words anywhere in the transcript
+        let text = format!(" {} ", normalize(text));
+        let says = |phrase: &str| !phrase.is_empty() && text.contains(&format!(" {} ", phrase));
+        if self.is_muted() {
+            if says(&self.resume_phrase) && self.resume() {
+                Heard::Resume
+            } else {
+                Heard::Drop
+            }
+        } else if says(&self.mute_phrase) {
+            self.muted.store(true, Ordering::Relaxed);
+            Heard::Mute
+        } else {
+            Heard::Pass
+        }
+    }
+}
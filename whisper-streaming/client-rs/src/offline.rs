@@ -0,0 +1,98 @@
+//! Utterances recorded while the server is unreachable, sent in order once
+//! it is back. Held in memory up to a budget, then spilled to WAV files.
+
+use anyhow::{Context, Result};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub struct Utterance {
+    pub id: Uuid,
+    pub sample_rate: u32,
+    pub audio: Vec<f32>,
+}
+
+pub struct OfflineQueue {
+    /// Oldest utterances; everything spilled is newer
+    memory: VecDeque<Utterance>,
+    memory_samples: usize,
+    max_memory_samples: usize,
+    spill_dir: Option<PathBuf>,
+    spilled: VecDeque<(Uuid, PathBuf)>,
+}
+
+impl OfflineQueue {
+    pub fn new(max_memory_samples: usize, spill_dir: Option<PathBuf>) -> Self {
+        Self {
+            memory: VecDeque::new(),
+            memory_samples: 0,
+            max_memory_samples,
+            spill_dir,
+            spilled: VecDeque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.memory.len() + self.spilled.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Queue an utterance. Returns false if it had to be dropped: memory is
+    /// full and there is no spill directory.
+    pub fn push(&mut self, utterance: Utterance) -> Result<bool> {
+        if self.spilled.is_empty()
+            && self.memory_samples + utterance.audio.len() <= self.max_memory_samples
+        {
+            self.memory_samples += utterance.audio.len();
+            self.memory.push_back(utterance);
+            return Ok(true);
+        }
+        let Some(dir) = &self.spill_dir else {
+            return Ok(false);
+        };
+        std::fs::create_dir_all(dir).with_context(|| format!("Cannot create {}", dir.display()))?;
+        let path = dir.join(format!("{}.wav", utterance.id));
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: utterance.sample_rate,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec)
+            .with_context(|| format!("Cannot write {}", path.display()))?;
+        for &s in &utterance.audio {
+            writer.write_sample(s)?;
+        }
+        writer.finalize()?;
+        self.spilled.push_back((utterance.id, path));
+        Ok(true)
+    }
+
+    /// The oldest queued utterance; spilled files are removed once read.
+    pub fn pop(&mut self) -> Result<Option<Utterance>> {
+        if let Some(utterance) = self.memory.pop_front() {
+            self.memory_samples -= utterance.audio.len();
+            return Ok(Some(utterance));
+        }
+        let Some((id, path)) = self.spilled.pop_front() else {
+            return Ok(None);
+        };
+        let (audio, sample_rate) = crate::file::load_wav(&path)?;
+        let _ = std::fs::remove_file(&path);
+        Ok(Some(Utterance {
+            id,
+            sample_rate,
+            audio,
+        }))
+    }
+
+    /// Put back an utterance that couldn't be sent, ahead of the rest.
+    pub fn unpop(&mut self, utterance: Utterance) {
+        self.memory_samples += utterance.audio.len();
+        self.memory.push_front(utterance);
+    }
+}
@@ -0,0 +1,125 @@
+//! Adapter for OpenAI's realtime transcription WebSocket API: the session is
+//! configured once, then each utterance is appended to the input buffer and
+//! committed, and the transcript arrives as events.
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::{HeaderName, HeaderValue};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::connection::{self, ServerResponse, WsRead, WsStream, WsWrite};
+use crate::dsp;
+use crate::Args;
+
+/// The API only takes 24kHz 16-bit mono PCM
+const SAMPLE_RATE: u32 = 24000;
+/// Samples per `input_audio_buffer.append` event, unless --frames-per-message
+const APPEND_SAMPLES: usize = SAMPLE_RATE as usize;
+
+#[derive(Deserialize)]
+struct Event {
+    #[serde(rename = "type")]
+    event_type: String,
+    transcript: Option<String>,
+}
+
+/// Connect with the key from --api-key / --token or OPENAI_API_KEY and
+/// configure transcription; turns are ended by this client's VAD.
+pub async fn open(args: &Args) -> Result<WsStream> {
+    let key = args
+        .api_key
+        .clone()
+        .or_else(|| args.token.clone())
+        .or_else(|| std::env::var("OPENAI_API_KEY").ok())
+        .context("--backend openai needs --api-key or OPENAI_API_KEY")?;
+    let mut request = args.openai_url.as_str().into_client_request()?;
+    let headers = request.headers_mut();
+    headers.insert(
+        "authorization",
+        HeaderValue::from_str(&format!("Bearer {}", key))?,
+    );
+    headers.insert("openai-beta", HeaderValue::from_static("realtime=v1"));
+    for (name, value) in &args.headers {
+        headers.append(
+            HeaderName::from_bytes(name.as_bytes())?,
+            HeaderValue::from_str(value)?,
+        );
+    }
+    let (mut stream, _) = connection::connect(request, args).await?;
+
+    let mut transcription = json!({ "model": args.openai_model });
+    if let Some(language) = &args.openai_language {
+        transcription["language"] = json!(language);
+    }
+    let update = json!({
+        "type": "transcription_session.update",
+        "session": {
+            "input_audio_format": "pcm16",
+            "input_audio_transcription": transcription,
+            "turn_detection": null,
+        },
+    });
+    stream.send(Message::Text(update.to_string())).await?;
+    Ok(stream)
+}
+
+/// Transcribe one utterance. An error means the connection is gone; an
+/// API error event gives `None`.
+pub async fn transcribe(
+    write: &mut WsWrite,
+    read: &mut WsRead,
+    audio: &[f32],
+    sample_rate: u32,
+    message_ms: Option<u32>,
+    send_timeout: Option<Duration>,
+) -> Result<Option<ServerResponse>> {
+    let audio = dsp::resample(audio, sample_rate, SAMPLE_RATE);
+    let per_message = connection::message_samples(message_ms, SAMPLE_RATE, APPEND_SAMPLES);
+    for chunk in audio.chunks(per_message) {
+        let bytes: Vec<u8> = dsp::f32_to_i16(chunk, false)
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let append = json!({
+            "type": "input_audio_buffer.append",
+            "audio": base64::engine::general_purpose::STANDARD.encode(&bytes),
+        });
+        connection::send_within(write, Message::Text(append.to_string()), send_timeout).await?;
+    }
+    let commit = json!({ "type": "input_audio_buffer.commit" });
+    connection::send_within(write, Message::Text(commit.to_string()), send_timeout).await?;
+
+    loop {
+        let text = match read.next().await {
+            Some(Ok(Message::Text(text))) => text,
+            Some(Ok(Message::Close(_))) | None => anyhow::bail!("connection closed"),
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => return Err(e.into()),
+        };
+        let Ok(event) = serde_json::from_str::<Event>(&text) else {
+            continue;
+        };
+        match event.event_type.as_str() {
+            "conversation.item.input_audio_transcription.completed" => {
+                return Ok(Some(ServerResponse {
+                    msg_type: "result".to_string(),
+                    text: event.transcript,
+                    sample: None,
+                    message: None,
+                    utterance_id: None,
+                    words: Vec::new(),
+                    confidence: None,
+                    speaker: None,
+                }))
+            }
+            "conversation.item.input_audio_transcription.failed" | "error" => return Ok(None),
+            // Session updates, commits and transcript deltas
+            _ => {}
+        }
+    }
+}
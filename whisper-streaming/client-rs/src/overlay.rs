@@ -0,0 +1,74 @@
+//! Caption overlay (--http-port): a local page showing the live partial and
+//! the last few finals on a transparent background, for use as an OBS
+//! browser source. The page follows the transcript events as Server-Sent
+//! Events from `/events`.
+
+use anyhow::{Context, Result};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+
+use crate::{httpd, Args};
+
+const PAGE: &str = include_str!("overlay.html");
+
+/// Serve the page and event stream on localhost, fed from `events`.
+pub fn serve(args: &Args, port: u16, events: broadcast::Sender<String>) -> Result<()> {
+    let defaults = serde_json::json!({
+        "font": args.caption_font,
+        "size": args.caption_size,
+        "lines": args.caption_lines,
+    });
+    let page = PAGE.replace("/*DEFAULTS*/", &defaults.to_string());
+    let listener = httpd::listen(&format!("127.0.0.1:{}", port))
+        .with_context(|| format!("Cannot serve the caption overlay on port {}", port))?;
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let page = page.clone();
+            let events = events.subscribe();
+            tokio::spawn(async move {
+                match httpd::request_path(&mut stream).await.as_deref() {
+                    Some("/") => {
+                        httpd::respond(
+                            &mut stream,
+                            "200 OK",
+                            "text/html; charset=utf-8",
+                            page.as_bytes(),
+                        )
+                        .await
+                    }
+                    Some("/events") => stream_events(stream, events).await,
+                    Some(_) => {
+                        httpd::respond(&mut stream, "404 Not Found", "text/plain", b"Not found")
+                            .await
+                    }
+                    None => {}
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+/// Forward events to the page until it goes away.
+async fn stream_events(mut stream: TcpStream, mut events: broadcast::Receiver<String>) {
+    let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nAccess-Control-Allow-Origin: *\r\n\r\n";
+    if stream.write_all(head.as_bytes()).await.is_err() {
+        return;
+    }
+    loop {
+        let line = match events.recv().await {
+            Ok(line) => line,
+            // A page that fell behind only misses some captions
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        if stream
+            .write_all(format!("data: {}\n\n", line).as_bytes())
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+}
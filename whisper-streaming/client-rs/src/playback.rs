@@ -0,0 +1,100 @@
+use anyhow::{Context, Result};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::dsp::{resample, EchoReference};
+
+/// Audio playing on the output device; dropping it closes the stream.
+pub struct Playback {
+    _stream: cpal::Stream,
+    control: PlaybackControl,
+}
+
+impl Playback {
+    pub fn control(&self) -> PlaybackControl {
+        self.control.clone()
+    }
+}
+
+/// Lets another task cut playback short, e.g. when the user barges in.
+#[derive(Clone, Default)]
+pub struct PlaybackControl {
+    stopped: Arc<AtomicBool>,
+}
+
+impl PlaybackControl {
+    /// Silence the rest of the audio. Returns false if it was already stopped.
+    pub fn stop(&self) -> bool {
+        !self.stopped.swap(true, Ordering::Relaxed)
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+}
+
+/// Play mono samples on the default output device. Silence is emitted once
+/// the samples run out or playback is stopped. Played audio is mirrored into
+/// `echo_ref` for echo cancellation.
+pub fn play(
+    samples: &[f32],
+    sample_rate: u32,
+    echo_ref: Option<EchoReference>,
+) -> Result<Playback> {
+    let host = cpal::default_host();
+    let device = host
+        .default_output_device()
+        .context("No output device available")?;
+
+    let config: cpal::StreamConfig = device.default_output_config()?.into();
+    let channels = config.channels as usize;
+    let output_rate = config.sample_rate.0;
+    let reference = echo_ref
+        .as_ref()
+        .map(|r| resample(samples, sample_rate, r.sample_rate()))
+        .unwrap_or_default();
+    let samples = resample(samples, sample_rate, output_rate);
+    let mut pos = 0;
+    let mut ref_pos = 0;
+    // Where playback ends, moved up if it's stopped early
+    let mut end = samples.len();
+    let mut ref_len = reference.len();
+    let control = PlaybackControl::default();
+    let stop = control.clone();
+
+    let stream = device.build_output_stream(
+        &config,
+        move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+            if stop.is_stopped() {
+                end = end.min(pos);
+                ref_len = ref_len.min(ref_pos);
+            }
+            for frame in data.chunks_mut(channels) {
+                let sample = if pos < end { samples[pos] } else { 0.0 };
+                pos += 1;
+                frame.fill(sample);
+            }
+            if let Some(echo_ref) = &echo_ref {
+                let ref_end =
+                    (pos as u64 * echo_ref.sample_rate() as u64 / output_rate as u64) as usize;
+                let played = reference
+                    .get(ref_pos..ref_end.min(ref_len))
+                    .unwrap_or_default();
+                echo_ref.push(played);
+                // Keep the reference clock running with silence after the end
+                let silence = ref_end.saturating_sub(ref_len.max(ref_pos));
+                echo_ref.push(&vec![0.0; silence]);
+                ref_pos = ref_end;
+            }
+        },
+        |err| eprintln!("Playback error: {}", err),
+        None,
+    )?;
+
+    stream.play()?;
+    Ok(Playback {
+        _stream: stream,
+        control,
+    })
+}
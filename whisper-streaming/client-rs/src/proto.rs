@@ -0,0 +1,146 @@
+//! Protobuf messages of `proto/whisper.proto` (package `whisper.v1`),
+//! written out by hand so building doesn't need protoc. Tags must match the
+//! schema.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum SampleFormat {
+    F32 = 0,
+    I16 = 1,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct AudioFrame {
+    #[prost(string, tag = "1")]
+    pub utterance_id: String,
+    #[prost(uint32, tag = "2")]
+    pub sample_rate: u32,
+    #[prost(enumeration = "SampleFormat", tag = "3")]
+    pub format: i32,
+    #[prost(bytes = "vec", tag = "4")]
+    pub samples: Vec<u8>,
+    #[prost(bool, tag = "5")]
+    pub partial: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct VadEnd {
+    #[prost(string, tag = "1")]
+    pub utterance_id: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SessionResume {
+    #[prost(string, tag = "1")]
+    pub session_id: String,
+    #[prost(string, tag = "2")]
+    pub last_utterance_id: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SessionConfig {
+    #[prost(uint32, tag = "1")]
+    pub sample_rate: u32,
+    #[prost(string, tag = "2")]
+    pub language: String,
+    #[prost(string, tag = "3")]
+    pub model: String,
+    #[prost(string, repeated, tag = "4")]
+    pub hotwords: Vec<String>,
+    #[prost(bool, tag = "5")]
+    pub word_timestamps: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ClientMessage {
+    #[prost(oneof = "client_message::Message", tags = "1, 2, 3, 4")]
+    pub message: Option<client_message::Message>,
+}
+
+pub mod client_message {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Message {
+        #[prost(message, tag = "1")]
+        AudioFrame(super::AudioFrame),
+        #[prost(message, tag = "2")]
+        VadEnd(super::VadEnd),
+        #[prost(message, tag = "3")]
+        SessionResume(super::SessionResume),
+        #[prost(message, tag = "4")]
+        SessionConfig(super::SessionConfig),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Partial {
+    #[prost(string, tag = "1")]
+    pub utterance_id: String,
+    #[prost(string, tag = "2")]
+    pub text: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Word {
+    #[prost(string, tag = "1")]
+    pub word: String,
+    #[prost(float, tag = "2")]
+    pub start: f32,
+    #[prost(float, tag = "3")]
+    pub end: f32,
+    #[prost(float, optional, tag = "4")]
+    pub probability: Option<f32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FinalResult {
+    #[prost(string, tag = "1")]
+    pub utterance_id: String,
+    #[prost(string, tag = "2")]
+    pub text: String,
+    #[prost(string, tag = "3")]
+    pub language: String,
+    #[prost(float, tag = "4")]
+    pub processing_time_ms: f32,
+    #[prost(message, repeated, tag = "5")]
+    pub words: Vec<Word>,
+    #[prost(float, optional, tag = "6")]
+    pub confidence: Option<f32>,
+    #[prost(string, tag = "7")]
+    pub speaker: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Noise {
+    #[prost(string, tag = "1")]
+    pub utterance_id: String,
+    #[prost(string, tag = "2")]
+    pub sample: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Error {
+    #[prost(string, tag = "1")]
+    pub utterance_id: String,
+    #[prost(string, tag = "2")]
+    pub message: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ServerMessage {
+    #[prost(oneof = "server_message::Message", tags = "1, 2, 3, 4")]
+    pub message: Option<server_message::Message>,
+}
+
+pub mod server_message {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Message {
+        #[prost(message, tag = "1")]
+        Partial(super::Partial),
+        #[prost(message, tag = "2")]
+        FinalResult(super::FinalResult),
+        #[prost(message, tag = "3")]
+        Noise(super::Noise),
+        #[prost(message, tag = "4")]
+        Error(super::Error),
+    }
+}
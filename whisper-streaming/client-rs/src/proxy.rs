@@ -0,0 +1,143 @@
+//! Reaching the server through an HTTP (CONNECT) or SOCKS5 proxy, from
+//! --proxy or the usual HTTPS_PROXY / HTTP_PROXY / ALL_PROXY / NO_PROXY
+//! variables.
+
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_socks::tcp::Socks5Stream;
+use url::Url;
+
+/// The proxy for a connection to `host` over `scheme` (`ws` or `wss`), if any.
+pub fn for_target(scheme: &str, host: &str, explicit: Option<&str>) -> Result<Option<Url>> {
+    let proxy = match explicit {
+        Some(proxy) => Some(proxy.to_string()),
+        None if bypassed(host) => None,
+        None => {
+            let names: &[&str] = if scheme == "wss" {
+                &["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"]
+            } else {
+                &["HTTP_PROXY", "http_proxy", "ALL_PROXY", "all_proxy"]
+            };
+            names
+                .iter()
+                .find_map(|name| std::env::var(name).ok())
+                .filter(|v| !v.is_empty())
+        }
+    };
+    proxy
+        .map(|proxy| Url::parse(&proxy).with_context(|| format!("Invalid proxy URL {}", proxy)))
+        .transpose()
+}
+
+/// Whether NO_PROXY lists `host`: `*`, the host itself or a parent domain.
+fn bypassed(host: &str) -> bool {
+    let Some(list) = std::env::var("NO_PROXY")
+        .ok()
+        .or_else(|| std::env::var("no_proxy").ok())
+    else {
+        return false;
+    };
+    list.split(',').map(str::trim).any(|entry| {
+        let entry = entry.trim_start_matches('.');
+        entry == "*"
+            || (!entry.is_empty() && (host == entry || host.ends_with(&format!(".{}", entry))))
+    })
+}
+
+/// Open a TCP stream to `host:port` through `proxy`.
+pub async fn tunnel(proxy: &Url, host: &str, port: u16) -> Result<TcpStream> {
+    let proxy_host = proxy.host_str().context("Proxy URL has no host")?;
+    let credentials = (!proxy.username().is_empty()).then(|| {
+        let decode = |s: &str| {
+            percent_encoding::percent_decode_str(s)
+                .decode_utf8_lossy()
+                .into_owned()
+        };
+        (
+            decode(proxy.username()),
+            decode(proxy.password().unwrap_or_default()),
+        )
+    });
+    match proxy.scheme() {
+        "http" => {
+            let proxy_port = proxy.port().unwrap_or(8080);
+            let mut stream = TcpStream::connect((proxy_host, proxy_port))
+                .await
+                .with_context(|| format!("Cannot reach proxy {}:{}", proxy_host, proxy_port))?;
+            http_connect(&mut stream, host, port, credentials).await?;
+            Ok(stream)
+        }
+        // socks5 resolves the server name locally, socks5h leaves it to the proxy
+        scheme @ ("socks5" | "socks5h") => {
+            let proxy_addr = (proxy_host, proxy.port().unwrap_or(1080));
+            let target = if scheme == "socks5" {
+                tokio::net::lookup_host((host, port))
+                    .await?
+                    .next()
+                    .with_context(|| format!("Cannot resolve {}", host))?
+                    .to_string()
+            } else {
+                format!("{}:{}", host, port)
+            };
+            let stream = match credentials {
+                Some((user, password)) => {
+                    Socks5Stream::connect_with_password(
+                        proxy_addr,
+                        target.as_str(),
+                        &user,
+                        &password,
+                    )
+                    .await?
+                }
+                None => Socks5Stream::connect(proxy_addr, target.as_str()).await?,
+            };
+            Ok(stream.into_inner())
+        }
+        other => bail!(
+            "Unsupported proxy scheme `{}` (use http, socks5 or socks5h)",
+            other
+        ),
+    }
+}
+
+async fn http_connect(
+    stream: &mut TcpStream,
+    host: &str,
+    port: u16,
+    credentials: Option<(String, String)>,
+) -> Result<()> {
+    let authority = if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    };
+    let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", authority);
+    if let Some((user, password)) = credentials {
+        let token =
+            base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", user, password));
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", token));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // Read the response head byte by byte so nothing after it is consumed
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() > 8192 {
+            bail!("Proxy response too long");
+        }
+        let byte = stream
+            .read_u8()
+            .await
+            .context("Proxy closed the connection")?;
+        head.push(byte);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let status = head.lines().next().unwrap_or_default();
+    if status.split_whitespace().nth(1) != Some("200") {
+        bail!("Proxy refused the tunnel: {}", status);
+    }
+    Ok(())
+}
@@ -0,0 +1,69 @@
+//! Scheduled quiet hours: local time-of-day windows during which the client
+//! drops all audio.
+
+use chrono::{Local, Timelike};
+use std::fmt;
+
+/// A daily window in minutes after midnight; may wrap past midnight.
+#[derive(Clone, Copy, Debug)]
+pub struct QuietWindow {
+    start: u32,
+    end: u32,
+}
+
+impl QuietWindow {
+    fn contains(&self, minute: u32) -> bool {
+        if self.start < self.end {
+            (self.start..self.end).contains(&minute)
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+}
+
+impl fmt::Display for QuietWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}-{:02}:{:02}",
+            self.start / 60,
+            self.start % 60,
+            self.end / 60,
+            self.end % 60
+        )
+    }
+}
+
+fn parse_time(s: &str) -> Result<u32, String> {
+    let (h, m) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected HH:MM, got `{}`", s))?;
+    let h: u32 = h.parse().map_err(|_| format!("bad hour in `{}`", s))?;
+    let m: u32 = m.parse().map_err(|_| format!("bad minute in `{}`", s))?;
+    if h > 23 || m > 59 {
+        return Err(format!("`{}` is not a time of day", s));
+    }
+    Ok(h * 60 + m)
+}
+
+/// Parse `HH:MM-HH:MM`, e.g. `22:30-07:00`.
+pub fn parse_window(s: &str) -> Result<QuietWindow, String> {
+    let (start, end) = s
+        .split_once('-')
+        .ok_or_else(|| format!("expected HH:MM-HH:MM, got `{}`", s))?;
+    let window = QuietWindow {
+        start: parse_time(start.trim())?,
+        end: parse_time(end.trim())?,
+    };
+    if window.start == window.end {
+        return Err(format!("`{}` is an empty window", s));
+    }
+    Ok(window)
+}
+
+/// The window that covers the current local time, if any.
+pub fn active(windows: &[QuietWindow]) -> Option<QuietWindow> {
+    let now = Local::now();
+    let minute = now.hour() * 60 + now.minute();
+    windows.iter().copied().find(|w| w.contains(minute))
+}
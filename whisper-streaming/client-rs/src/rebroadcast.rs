@@ -0,0 +1,51 @@
+//! Local WebSocket rebroadcast (--ws-broadcast): every transcript and
+//! speech event goes out as a JSON text message to each connected client,
+//! so dashboards and bots can follow along without their own upstream
+//! connection. Whatever clients send is ignored.
+
+use anyhow::{Context, Result};
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::httpd;
+
+/// Accept subscribers on `addr` and feed them from `events`.
+pub fn serve(addr: &str, events: broadcast::Sender<String>) -> Result<()> {
+    let listener = httpd::listen(addr)
+        .with_context(|| format!("Cannot serve the event rebroadcast on {}", addr))?;
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(forward(stream, events.subscribe()));
+        }
+    });
+    Ok(())
+}
+
+/// Send events to one subscriber until it disconnects.
+async fn forward(stream: TcpStream, mut events: broadcast::Receiver<String>) {
+    let Ok(ws) = tokio_tungstenite::accept_async(stream).await else {
+        return;
+    };
+    let (mut tx, mut rx) = ws.split();
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(line) => {
+                    if tx.send(Message::Text(line)).await.is_err() {
+                        return;
+                    }
+                }
+                // A slow subscriber only misses some events
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            // Reading answers pings and notices the client leaving
+            msg = rx.next() => match msg {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
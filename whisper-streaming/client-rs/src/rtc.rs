@@ -0,0 +1,247 @@
+//! WebRTC transport: audio goes out as Opus on an RTP track and results come
+//! back on a data channel, so the server gets the media stack's jitter
+//! buffering and ICE NAT traversal. The offer and answer are exchanged over
+//! a WebSocket at `<server-url>/ws/webrtc`, with all candidates gathered up
+//! front.
+
+use anyhow::{bail, Context, Result};
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::tungstenite::Message;
+use uuid::Uuid;
+use webrtc::api::interceptor_registry::register_default_interceptors;
+use webrtc::api::media_engine::{MediaEngine, MIME_TYPE_OPUS};
+use webrtc::api::APIBuilder;
+use webrtc::data_channel::data_channel_message::DataChannelMessage;
+use webrtc::data_channel::RTCDataChannel;
+use webrtc::ice_transport::ice_server::RTCIceServer;
+use webrtc::interceptor::registry::Registry;
+use webrtc::media::Sample;
+use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
+use webrtc::track::track_local::TrackLocal;
+
+use crate::connection::{self, ServerResponse};
+use crate::Args;
+
+/// Duration of each Opus packet
+const PACKET: Duration = Duration::from_millis(20);
+/// How long the server gets to answer the offer and open the data channel
+const SETUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize)]
+struct Answer {
+    #[serde(rename = "type")]
+    msg_type: String,
+    sdp: Option<String>,
+}
+
+pub struct RtcStream {
+    peer: Arc<RTCPeerConnection>,
+    track: Arc<TrackLocalStaticSample>,
+    channel: Arc<RTCDataChannel>,
+    replies: mpsc::UnboundedReceiver<String>,
+}
+
+impl RtcStream {
+    /// Negotiate a peer connection with the server at `url`.
+    pub async fn open(url: &str, args: &Args) -> Result<Self> {
+        let mut media = MediaEngine::default();
+        media.register_default_codecs()?;
+        let registry = register_default_interceptors(Registry::new(), &mut media)?;
+        let api = APIBuilder::new()
+            .with_media_engine(media)
+            .with_interceptor_registry(registry)
+            .build();
+        let config = RTCConfiguration {
+            ice_servers: vec![RTCIceServer {
+                urls: args.ice_servers.clone(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let peer = Arc::new(api.new_peer_connection(config).await?);
+
+        let track = Arc::new(TrackLocalStaticSample::new(
+            RTCRtpCodecCapability {
+                mime_type: MIME_TYPE_OPUS.to_string(),
+                clock_rate: 48000,
+                channels: 2,
+                ..Default::default()
+            },
+            "audio".to_string(),
+            env!("CARGO_PKG_NAME").to_string(),
+        ));
+        let sender = peer
+            .add_track(Arc::clone(&track) as Arc<dyn TrackLocal + Send + Sync>)
+            .await?;
+        // RTCP has to be read for the interceptors (NACK, reports) to run
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 1500];
+            while sender.read(&mut buf).await.is_ok() {}
+        });
+
+        let channel = peer.create_data_channel("results", None).await?;
+        let (reply_tx, replies) = mpsc::unbounded_channel();
+        channel.on_message(Box::new(move |message: DataChannelMessage| {
+            let _ = reply_tx.send(String::from_utf8_lossy(&message.data).into_owned());
+            Box::pin(async {})
+        }));
+        let (open_tx, open_rx) = oneshot::channel();
+        let open_tx = std::sync::Mutex::new(Some(open_tx));
+        channel.on_open(Box::new(move || {
+            if let Some(tx) = open_tx.lock().unwrap().take() {
+                let _ = tx.send(());
+            }
+            Box::pin(async {})
+        }));
+
+        let offer = peer.create_offer(None).await?;
+        let mut gathered = peer.gathering_complete_promise().await;
+        peer.set_local_description(offer).await?;
+        let _ = gathered.recv().await;
+        let offer = peer
+            .local_description()
+            .await
+            .context("No local description after ICE gathering")?;
+
+        tokio::time::timeout(SETUP_TIMEOUT, async {
+            let answer = signal(url, args, &offer.sdp).await?;
+            peer.set_remote_description(RTCSessionDescription::answer(answer)?)
+                .await?;
+            open_rx.await.context("Data channel closed before opening")
+        })
+        .await
+        .context("WebRTC setup timed out")??;
+
+        Ok(Self {
+            peer,
+            track,
+            channel,
+            replies,
+        })
+    }
+
+    /// An error once ICE or DTLS has given up on the peer.
+    pub fn check(&self) -> Result<()> {
+        match self.peer.connection_state() {
+            state @ (RTCPeerConnectionState::Failed
+            | RTCPeerConnectionState::Disconnected
+            | RTCPeerConnectionState::Closed) => bail!("peer connection {}", state),
+            _ => Ok(()),
+        }
+    }
+
+    /// Send a JSON control message on the data channel.
+    pub async fn send(&self, message: String) -> Result<()> {
+        self.check()?;
+        self.channel.send_text(message).await?;
+        Ok(())
+    }
+
+    /// Play `audio` into the track in real time, mark the end of the
+    /// utterance and wait for its reply.
+    pub async fn transcribe(
+        &mut self,
+        audio: &[f32],
+        sample_rate: u32,
+        utterance_id: Uuid,
+        bitrate: u32,
+        final_timeout: Option<Duration>,
+        notices: &mut Vec<ServerResponse>,
+    ) -> Result<Option<ServerResponse>> {
+        self.check()?;
+        let start = json!({ "type": "utterance", "utterance_id": utterance_id, "sample_rate": sample_rate });
+        self.send(start.to_string()).await?;
+        // The receiver's jitter buffer only holds a few packets, so they go
+        // out at the rate they were recorded
+        let mut pacing = tokio::time::interval(PACKET);
+        for packet in connection::opus_frames(audio, sample_rate, bitrate)? {
+            pacing.tick().await;
+            self.track
+                .write_sample(&Sample {
+                    data: packet.into(),
+                    duration: PACKET,
+                    ..Default::default()
+                })
+                .await?;
+        }
+        let end = json!({ "type": "vad_end", "utterance_id": utterance_id }).to_string();
+        self.send(end.clone()).await?;
+
+        let mut retried = false;
+        let mut deadline = connection::deadline(final_timeout);
+        loop {
+            let Some(next) = connection::until(deadline, self.replies.recv()).await else {
+                let timeout = final_timeout.unwrap_or_default();
+                notices.push(ServerResponse::unanswered(utterance_id, timeout, retried));
+                if retried {
+                    return Ok(None);
+                }
+                retried = true;
+                deadline = connection::deadline(final_timeout);
+                self.send(end.clone()).await?;
+                continue;
+            };
+            let Some(text) = next else {
+                bail!("data channel closed");
+            };
+            let Ok(reply) = serde_json::from_str::<ServerResponse>(&text) else {
+                return Ok(None);
+            };
+            // Partials for this utterance come ahead of its result
+            if reply.msg_type == "partial" || reply.is_stale(utterance_id) {
+                continue;
+            }
+            if let Some(reply) = connection::screen(notices, Some(reply)) {
+                return Ok(reply);
+            }
+        }
+    }
+}
+
+impl Drop for RtcStream {
+    fn drop(&mut self) {
+        let peer = Arc::clone(&self.peer);
+        tokio::spawn(async move {
+            let _ = peer.close().await;
+        });
+    }
+}
+
+/// Exchange the offer for the server's answer over the signalling socket.
+async fn signal(url: &str, args: &Args, sdp: &str) -> Result<String> {
+    let request = connection::handshake_request(url, args)?;
+    let (mut stream, _) = connection::connect(request, args).await?;
+    let offer = json!({ "type": "offer", "sdp": sdp });
+    stream.send(Message::Text(offer.to_string())).await?;
+    loop {
+        let text = match stream.next().await {
+            Some(Ok(Message::Text(text))) => text,
+            Some(Ok(Message::Close(_))) | None => bail!("Signalling closed without an answer"),
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => return Err(e.into()),
+        };
+        match serde_json::from_str::<Answer>(&text) {
+            Ok(Answer {
+                msg_type,
+                sdp: Some(sdp),
+            }) if msg_type == "answer" => {
+                let _ = stream.close(None).await;
+                return Ok(sdp);
+            }
+            Ok(Answer { msg_type, .. }) if msg_type == "error" => {
+                bail!("Server rejected the offer: {}", text)
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::{bail, Result};
+use std::time::Duration;
+
+use crate::capture::Capture;
+use crate::config::StageConfig;
+use crate::dsp::EchoReference;
+use crate::file::load_wav;
+use crate::{frontend, playback, run_session, Args, SelftestArgs, SessionInput};
+
+/// Play a known phrase through the speakers while capturing the mic, and
+/// report what the server transcribed.
+pub async fn run(args: &Args, opts: &SelftestArgs) -> Result<()> {
+    let (phrase, phrase_rate) = load_wav(&opts.wav)?;
+    let phrase_secs = phrase.len() as f64 / phrase_rate as f64;
+
+    say!(
+        "[selftest] Phrase: {} ({:.1}s)",
+        opts.wav.display(),
+        phrase_secs
+    );
+
+    let (capture, audio_rx) = Capture::start(None, args.config.channel_weights(None))?;
+    let uses_aec = frontend::stage_chain(args)
+        .iter()
+        .any(|s| matches!(s, StageConfig::Aec { .. }));
+    let echo_ref = uses_aec.then(|| EchoReference::new(args.sample_rate));
+    let playback = playback::play(&phrase, phrase_rate, echo_ref.clone())?;
+
+    let wait = Duration::from_secs_f64(phrase_secs)
+        + Duration::from_millis(args.silence_threshold_ms as u64)
+        + Duration::from_secs(opts.grace_secs);
+    let stop = async move {
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = tokio::signal::ctrl_c() => {}
+        }
+    };
+
+    let input = SessionInput {
+        label: None,
+        audio_rx,
+        device_sample_rate: capture.sample_rate,
+        device_name: Some(capture.device_name.clone()),
+        echo_ref,
+        meter_tx: None,
+        status_tx: None,
+        talk: None,
+        barge_in: args.barge_in.then(|| playback.control()),
+        events: None,
+        subtitles: None,
+        transcript_log: None,
+        dictation: None,
+        mute: None,
+    };
+    let report = run_session(args, input, stop).await?;
+    drop(playback);
+    capture.stop();
+
+    say!("\n--- Selftest ---");
+    if report.transcripts.is_empty() {
+        bail!("No transcription came back (check mic/speaker levels and server)");
+    }
+    let heard = report.transcripts.join(" ");
+    say!("Heard: {}", heard);
+
+    if let Some(expected) = &opts.expect {
+        let score = word_match(expected, &heard);
+        say!("Expected: {}", expected);
+        say!("Word match: {:.0}%", score * 100.0);
+        if score < opts.min_match {
+            bail!(
+                "Selftest failed: match below {:.0}%",
+                opts.min_match * 100.0
+            );
+        }
+    }
+    say!("Selftest passed");
+    Ok(())
+}
+
+/// Fraction of expected words that appear in the heard text.
+fn word_match(expected: &str, heard: &str) -> f64 {
+    let normalize = |s: &str| -> Vec<String> {
+        s.split_whitespace()
+            .map(|w| {
+                w.chars()
+                    .filter(|c| c.is_alphanumeric())
+                    .flat_map(char::to_lowercase)
+                    .collect::<String>()
+            })
+            .filter(|w| !w.is_empty())
+            .collect()
+    };
+    let expected = normalize(expected);
+    if expected.is_empty() {
+        return 1.0;
+    }
+    let heard = normalize(heard);
+    let found = expected.iter().filter(|w| heard.contains(w)).count();
+    found as f64 / expected.len() as f64
+}
@@ -0,0 +1,99 @@
+//! The byte stream under a WebSocket: TCP, or a Unix domain socket for a
+//! server on the same machine (`--server-url unix:///path/to/sock`).
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+
+pub enum Socket {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+/// Socket path of a `unix://` server URL, stored in the handshake request's
+/// extensions for `connection::connect`.
+#[derive(Clone, Debug)]
+pub struct UnixPath(pub PathBuf);
+
+/// Split a `unix://` URL's path into the socket file and the HTTP path
+/// after it, e.g. `/run/whisper.sock/ws/transcribe`. The socket is the
+/// longest leading part that exists on disk; without one the whole path is
+/// taken as the socket so connecting reports it missing.
+pub fn split_unix_path(path: &str) -> (PathBuf, String) {
+    let full = Path::new(path);
+    for socket in full.ancestors() {
+        if socket.as_os_str().is_empty() || socket == Path::new("/") {
+            break;
+        }
+        if socket.exists() && !socket.is_dir() {
+            let rest = full.strip_prefix(socket).unwrap_or(Path::new(""));
+            return (socket.to_path_buf(), format!("/{}", rest.display()));
+        }
+    }
+    (full.to_path_buf(), "/".to_string())
+}
+
+impl Socket {
+    #[cfg(unix)]
+    pub async fn unix(path: &Path) -> io::Result<Self> {
+        Ok(Socket::Unix(UnixStream::connect(path).await?))
+    }
+
+    #[cfg(not(unix))]
+    pub async fn unix(_path: &Path) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Unix domain sockets need a Unix platform",
+        ))
+    }
+}
+
+impl AsyncRead for Socket {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Socket::Tcp(s) => Pin::new(s).poll_read(cx, buf),
+            #[cfg(unix)]
+            Socket::Unix(s) => Pin::new(s).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Socket {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Socket::Tcp(s) => Pin::new(s).poll_write(cx, buf),
+            #[cfg(unix)]
+            Socket::Unix(s) => Pin::new(s).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Socket::Tcp(s) => Pin::new(s).poll_flush(cx),
+            #[cfg(unix)]
+            Socket::Unix(s) => Pin::new(s).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Socket::Tcp(s) => Pin::new(s).poll_shutdown(cx),
+            #[cfg(unix)]
+            Socket::Unix(s) => Pin::new(s).poll_shutdown(cx),
+        }
+    }
+}
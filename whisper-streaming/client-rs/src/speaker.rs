@@ -0,0 +1,129 @@
+//! Rough speaker-change detection from pitch and level statistics of
+//! consecutive utterances, for servers without diarization, and labels for
+//! the speakers of servers with it.
+
+/// Voice statistics of one utterance.
+#[derive(Clone, Copy, Debug)]
+pub struct VoiceStats {
+    /// Median fundamental frequency over voiced frames
+    pub pitch_hz: f32,
+    /// Median level of voiced frames in dBFS
+    pub level_db: f32,
+}
+
+const FRAME_MS: u32 = 40;
+const HOP_MS: u32 = 20;
+const MIN_PITCH_HZ: f32 = 70.0;
+const MAX_PITCH_HZ: f32 = 400.0;
+/// Normalized autocorrelation peak needed to call a frame voiced
+const VOICING: f32 = 0.5;
+
+/// Measure pitch and level; `None` if the audio has no voiced frames.
+pub fn analyze(samples: &[f32], sample_rate: u32) -> Option<VoiceStats> {
+    let frame = (sample_rate * FRAME_MS / 1000) as usize;
+    let hop = (sample_rate * HOP_MS / 1000) as usize;
+    let min_lag = (sample_rate as f32 / MAX_PITCH_HZ) as usize;
+    let max_lag = ((sample_rate as f32 / MIN_PITCH_HZ) as usize).min(frame / 2);
+    if samples.len() < frame || min_lag >= max_lag {
+        return None;
+    }
+
+    let mut pitches = Vec::new();
+    let mut levels = Vec::new();
+    for start in (0..=samples.len() - frame).step_by(hop) {
+        let x = &samples[start..start + frame];
+        let energy: f32 = x.iter().map(|s| s * s).sum();
+        if energy <= 1e-6 {
+            continue;
+        }
+        let (lag, peak) = (min_lag..=max_lag)
+            .map(|lag| {
+                let r: f32 = x[..frame - lag]
+                    .iter()
+                    .zip(&x[lag..])
+                    .map(|(a, b)| a * b)
+                    .sum();
+                (lag, r / energy)
+            })
+            .fold((0, 0.0), |best, c| if c.1 > best.1 { c } else { best });
+        if peak >= VOICING {
+            pitches.push(sample_rate as f32 / lag as f32);
+            levels.push(10.0 * (energy / frame as f32).log10());
+        }
+    }
+    Some(VoiceStats {
+        pitch_hz: median(&mut pitches)?,
+        level_db: median(&mut levels)?,
+    })
+}
+
+fn median(values: &mut [f32]) -> Option<f32> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_unstable_by(f32::total_cmp);
+    Some(values[values.len() / 2])
+}
+
+/// Flags utterances whose voice differs markedly from the previous one.
+pub struct SpeakerTracker {
+    previous: Option<VoiceStats>,
+    threshold_semitones: f32,
+}
+
+impl SpeakerTracker {
+    /// A level jump this large counts towards a change; on its own it's
+    /// more likely the same speaker moving relative to the mic
+    const LEVEL_DB: f32 = 10.0;
+
+    pub fn new(threshold_semitones: f32) -> Self {
+        Self {
+            previous: None,
+            threshold_semitones,
+        }
+    }
+
+    /// Record an utterance's stats; true if it looks like a different speaker.
+    pub fn update(&mut self, stats: VoiceStats) -> bool {
+        let changed = self.previous.is_some_and(|prev| {
+            let semitones = (12.0 * (stats.pitch_hz / prev.pitch_hz).log2()).abs();
+            let level_db = (stats.level_db - prev.level_db).abs();
+            semitones >= self.threshold_semitones
+                || (semitones >= self.threshold_semitones / 2.0 && level_db >= Self::LEVEL_DB)
+        });
+        self.previous = Some(stats);
+        changed
+    }
+}
+
+/// ANSI colors given to speakers in turn
+const SPEAKER_COLORS: [u8; 6] = [36, 33, 35, 32, 34, 31];
+
+/// `S1`, `S2`, ... for a diarizing server's speaker IDs in order of first
+/// appearance, so each keeps its label and color for the session.
+#[derive(Default)]
+pub struct SpeakerLabels {
+    seen: Vec<String>,
+}
+
+impl SpeakerLabels {
+    /// `[S1] ` for `id`, in the speaker's color if `styled`; empty without one.
+    pub fn prefix(&mut self, id: Option<&str>, styled: bool) -> String {
+        let Some(id) = id else {
+            return String::new();
+        };
+        let index = match self.seen.iter().position(|seen| seen == id) {
+            Some(index) => index,
+            None => {
+                self.seen.push(id.to_string());
+                self.seen.len() - 1
+            }
+        };
+        let color = SPEAKER_COLORS[index % SPEAKER_COLORS.len()];
+        if styled {
+            format!("\x1b[{}m[S{}]\x1b[0m ", color, index + 1)
+        } else {
+            format!("[S{}] ", index + 1)
+        }
+    }
+}
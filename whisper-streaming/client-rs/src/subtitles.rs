@@ -0,0 +1,199 @@
+//! Final transcripts as subtitle cues (--srt, --vtt), timed by where each
+//! utterance lies in the captured audio so the file lines up with a
+//! recording of the session. Speech replayed after an outage has no
+//! position and is left out. With --word-timestamps an utterance is split
+//! into line-sized cues at its word timings.
+//!
+//! `--vtt http:ADDR` serves the WebVTT document instead, for web players
+//! and OBS browser sources that poll it for new captions.
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::connection::Word;
+use crate::{httpd, Args};
+
+/// Longest cue made from word timings, the usual subtitle line length
+const MAX_CUE_CHARS: usize = 42;
+
+#[derive(Clone, Copy)]
+enum Style {
+    Srt,
+    Vtt,
+}
+
+struct Track {
+    style: Style,
+    out: Box<dyn Write + Send>,
+    written: u32,
+}
+
+/// Shared cue writer; sessions for several devices number their cues together.
+#[derive(Clone)]
+pub struct Subtitles {
+    tracks: Arc<Mutex<Vec<Track>>>,
+}
+
+/// The WebVTT document served over HTTP.
+#[derive(Clone, Default)]
+struct Document(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Document {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if let Ok(mut doc) = self.0.lock() {
+            doc.extend_from_slice(buf);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Subtitles {
+    /// The tracks asked for by --srt and --vtt, if any.
+    pub fn for_args(args: &Args) -> Result<Option<Self>> {
+        let mut tracks = Vec::new();
+        if let Some(path) = &args.srt {
+            tracks.push(Track::new(Style::Srt, Box::new(create(path)?))?);
+        }
+        if let Some(dest) = &args.vtt {
+            match dest.strip_prefix("http:") {
+                Some(addr) => {
+                    let doc = Document::default();
+                    serve(addr, doc.clone())?;
+                    tracks.push(Track::new(Style::Vtt, Box::new(doc))?);
+                }
+                None => {
+                    let file = create(Path::new(dest))?;
+                    tracks.push(Track::new(Style::Vtt, Box::new(file))?);
+                }
+            }
+        }
+        Ok((!tracks.is_empty()).then(|| Self {
+            tracks: Arc::new(Mutex::new(tracks)),
+        }))
+    }
+
+    /// Append the cues for an utterance spanning `start..end`, flushed at
+    /// once so the file can be watched live. `device` prefixes the text.
+    pub fn cue(
+        &self,
+        start: Duration,
+        end: Duration,
+        device: Option<&str>,
+        text: &str,
+        words: &[Word],
+    ) {
+        let cues = if words.is_empty() {
+            vec![(start, end, text.to_string())]
+        } else {
+            split(start, words)
+        };
+        let Ok(mut tracks) = self.tracks.lock() else {
+            return;
+        };
+        for (start, end, text) in cues {
+            let text = match device {
+                Some(device) => format!("{}: {}", device, text),
+                None => text,
+            };
+            for track in tracks.iter_mut() {
+                track.written += 1;
+                let cue = format!(
+                    "{}\n{} --> {}\n{}\n\n",
+                    track.written,
+                    timestamp(start, track.style),
+                    timestamp(end, track.style),
+                    text
+                );
+                // A full disk shouldn't stop transcription
+                let _ = track
+                    .out
+                    .write_all(cue.as_bytes())
+                    .and_then(|_| track.out.flush());
+            }
+        }
+    }
+}
+
+impl Track {
+    fn new(style: Style, mut out: Box<dyn Write + Send>) -> Result<Self> {
+        if let Style::Vtt = style {
+            out.write_all(b"WEBVTT\n\n")?;
+            out.flush()?;
+        }
+        Ok(Self {
+            style,
+            out,
+            written: 0,
+        })
+    }
+}
+
+/// Runs of words up to MAX_CUE_CHARS long, timed from the first word's
+/// start to the last one's end.
+fn split(offset: Duration, words: &[Word]) -> Vec<(Duration, Duration, String)> {
+    let at = |secs: f64| offset + Duration::from_secs_f64(secs.max(0.0));
+    let mut cues: Vec<(Duration, Duration, String)> = Vec::new();
+    for word in words {
+        let text = word.word.trim();
+        if text.is_empty() {
+            continue;
+        }
+        match cues.last_mut() {
+            Some((_, end, line)) if line.len() + 1 + text.len() <= MAX_CUE_CHARS => {
+                *end = at(word.end);
+                line.push(' ');
+                line.push_str(text);
+            }
+            _ => cues.push((at(word.start), at(word.end), text.to_string())),
+        }
+    }
+    cues
+}
+
+fn create(path: &Path) -> Result<File> {
+    File::create(path).with_context(|| format!("Cannot create subtitles {}", path.display()))
+}
+
+/// `HH:MM:SS,mmm` for SRT, `HH:MM:SS.mmm` for WebVTT
+fn timestamp(at: Duration, style: Style) -> String {
+    let ms = at.as_millis();
+    let separator = match style {
+        Style::Srt => ',',
+        Style::Vtt => '.',
+    };
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        separator,
+        ms % 1000
+    )
+}
+
+/// Answer every request on `addr` with the current document.
+fn serve(addr: &str, doc: Document) -> Result<()> {
+    let listener =
+        httpd::listen(addr).with_context(|| format!("Cannot serve WebVTT on {}", addr))?;
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let doc = doc.clone();
+            tokio::spawn(async move {
+                // Whatever was asked for, the reply is the same
+                if httpd::request_path(&mut stream).await.is_some() {
+                    let body = doc.0.lock().map(|d| d.clone()).unwrap_or_default();
+                    httpd::respond(&mut stream, "200 OK", "text/vtt; charset=utf-8", &body).await;
+                }
+            });
+        }
+    });
+    Ok(())
+}
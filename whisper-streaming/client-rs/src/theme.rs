@@ -0,0 +1,71 @@
+//! Colors for the human-readable output (--theme). Nothing is styled where
+//! it wouldn't show: see `events::styled`, which also honors --no-color and
+//! `NO_COLOR`.
+
+use std::sync::OnceLock;
+
+use crate::events;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Theme {
+    /// For dark terminal backgrounds
+    Dark,
+    /// For light terminal backgrounds
+    Light,
+    /// Bold, dim, italic and underline only
+    Mono,
+}
+
+/// What a piece of output is.
+#[derive(Clone, Copy)]
+pub enum Role {
+    Partial,
+    /// Words of a partial that differ from the previous one
+    Changed,
+    Final,
+    /// Words of a final the server wasn't sure of (--low-confidence)
+    Unsure,
+    Latency,
+    Warning,
+}
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+pub fn set(theme: Theme) {
+    let _ = THEME.set(theme);
+}
+
+impl Theme {
+    /// SGR parameters for `role`
+    fn sgr(self, role: Role) -> &'static str {
+        match (self, role) {
+            (Theme::Dark, Role::Partial) => "90;3",
+            (Theme::Dark, Role::Changed) => "93;3",
+            (Theme::Dark, Role::Final) => "1",
+            (Theme::Dark, Role::Unsure) => "2;4",
+            (Theme::Dark, Role::Latency) => "36",
+            (Theme::Dark, Role::Warning) => "33",
+            (Theme::Light, Role::Partial) => "2;3",
+            (Theme::Light, Role::Changed) => "32;3",
+            (Theme::Light, Role::Final) => "1;34",
+            (Theme::Light, Role::Unsure) => "34;4",
+            (Theme::Light, Role::Latency) => "35",
+            (Theme::Light, Role::Warning) => "31",
+            (Theme::Mono, Role::Partial) => "3",
+            (Theme::Mono, Role::Changed) => "1;3",
+            (Theme::Mono, Role::Final) => "1",
+            (Theme::Mono, Role::Unsure) => "4",
+            (Theme::Mono, Role::Latency) => "2",
+            (Theme::Mono, Role::Warning) => "7",
+        }
+    }
+}
+
+/// `text` styled for `role`, or as is where styling wouldn't show.
+pub fn paint(role: Role, text: &str) -> String {
+    if !events::styled() {
+        return text.to_string();
+    }
+    let theme = THEME.get().copied().unwrap_or(Theme::Dark);
+    format!("\x1b[{}m{}\x1b[0m", theme.sgr(role), text)
+}
@@ -0,0 +1,41 @@
+//! Append-only log of final transcripts (--output), one timestamped line
+//! each. Lines are written straight to the file rather than buffered, so a
+//! crash loses nothing already transcribed.
+
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Shared log; sessions for several devices append to the same file.
+#[derive(Clone)]
+pub struct TranscriptLog {
+    file: Arc<Mutex<File>>,
+}
+
+impl TranscriptLog {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Cannot open transcript log {}", path.display()))?;
+        Ok(Self {
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// `2024-05-01T09:30:12.345+02:00 [device] text`
+    pub fn write(&self, device: Option<&str>, text: &str) {
+        let now = chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, false);
+        let line = match device {
+            Some(device) => format!("{} [{}] {}\n", now, device, text),
+            None => format!("{} {}\n", now, text),
+        };
+        if let Ok(mut file) = self.file.lock() {
+            // A full disk shouldn't stop transcription
+            let _ = file.write_all(line.as_bytes()).and_then(|_| file.flush());
+        }
+    }
+}
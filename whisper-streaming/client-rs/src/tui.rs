@@ -0,0 +1,220 @@
+//! Full-screen terminal UI (--tui): scrolling history of everything the
+//! client prints, and for each input its level meter, connection state,
+//! live partial and latency. Drawn on stderr, so `--format jsonl` still has
+//! stdout to itself.
+
+use anyhow::Result;
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::{execute, terminal};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Gauge, Paragraph};
+use ratatui::{Frame, Terminal};
+use std::collections::VecDeque;
+use std::io::Stderr;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+
+use crate::meter::{self, MeterReading, Status};
+
+/// Lines kept for scrolling back
+const HISTORY: usize = 2000;
+/// Lines moved by Page Up/Down
+const PAGE: usize = 10;
+
+/// One input's feeds.
+pub struct Lane {
+    pub label: Option<String>,
+    pub meter: watch::Receiver<MeterReading>,
+    pub status: watch::Receiver<Status>,
+}
+
+/// Raw mode on an alternate screen, restored when dropped, including when
+/// the UI task is aborted.
+struct Screen {
+    terminal: Terminal<CrosstermBackend<Stderr>>,
+}
+
+impl Screen {
+    fn enter() -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        if let Err(e) = execute!(std::io::stderr(), terminal::EnterAlternateScreen) {
+            let _ = terminal::disable_raw_mode();
+            return Err(e.into());
+        }
+        let terminal = Terminal::new(CrosstermBackend::new(std::io::stderr()))?;
+        Ok(Self { terminal })
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+        let _ = execute!(std::io::stderr(), terminal::LeaveAlternateScreen);
+        let _ = self.terminal.show_cursor();
+    }
+}
+
+/// Draw until the user quits (q, Esc or Ctrl-C), then stop the sessions.
+pub async fn run(
+    lanes: Vec<Lane>,
+    mut lines: mpsc::UnboundedReceiver<String>,
+    stop: watch::Sender<bool>,
+) -> Result<()> {
+    let result = draw_until_quit(&lanes, &mut lines).await;
+    let _ = stop.send(true);
+    result
+}
+
+async fn draw_until_quit(
+    lanes: &[Lane],
+    lines: &mut mpsc::UnboundedReceiver<String>,
+) -> Result<()> {
+    let mut screen = Screen::enter()?;
+    let mut history: VecDeque<String> = VecDeque::new();
+    // Lines scrolled back from the newest; 0 follows new output
+    let mut scroll: usize = 0;
+    let mut ticker = tokio::time::interval(Duration::from_millis(100));
+    loop {
+        ticker.tick().await;
+        while let Ok(text) = lines.try_recv() {
+            // Leading newlines only broke the old meter line
+            for line in text.trim_start_matches('\n').lines() {
+                if history.len() == HISTORY {
+                    history.pop_front();
+                }
+                history.push_back(line.to_string());
+                if scroll > 0 {
+                    scroll += 1;
+                }
+            }
+        }
+        while event::poll(Duration::ZERO)? {
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(())
+                }
+                KeyCode::Char('c') => {
+                    history.clear();
+                    scroll = 0;
+                }
+                KeyCode::Up | KeyCode::Char('k') => scroll += 1,
+                KeyCode::Down | KeyCode::Char('j') => scroll = scroll.saturating_sub(1),
+                KeyCode::PageUp => scroll += PAGE,
+                KeyCode::PageDown => scroll = scroll.saturating_sub(PAGE),
+                KeyCode::Home => scroll = history.len(),
+                KeyCode::End => scroll = 0,
+                _ => {}
+            }
+        }
+        scroll = scroll.min(history.len());
+        screen
+            .terminal
+            .draw(|frame| render(frame, lanes, &history, scroll))?;
+    }
+}
+
+fn render(frame: &mut Frame, lanes: &[Lane], history: &VecDeque<String>, scroll: usize) {
+    let mut rows = vec![Constraint::Min(3)];
+    rows.extend(lanes.iter().map(|_| Constraint::Length(4)));
+    rows.push(Constraint::Length(1));
+    let areas = Layout::vertical(rows).split(frame.area());
+
+    let height = areas[0].height.saturating_sub(2) as usize;
+    let end = history.len() - scroll;
+    let shown: Vec<Line> = history
+        .range(end.saturating_sub(height)..end)
+        .map(|line| Line::raw(line.as_str()))
+        .collect();
+    let title = match scroll {
+        0 => " Transcript ".to_string(),
+        n => format!(" Transcript ({} lines back) ", n),
+    };
+    frame.render_widget(
+        Paragraph::new(shown).block(Block::bordered().title(title)),
+        areas[0],
+    );
+
+    for (lane, &area) in lanes.iter().zip(&areas[1..]) {
+        render_lane(frame, lane, area);
+    }
+
+    let help = "q quit  ↑/↓ PgUp/PgDn Home/End scroll  c clear";
+    frame.render_widget(
+        Paragraph::new(help).style(Style::default().fg(Color::DarkGray)),
+        areas[areas.len() - 1],
+    );
+}
+
+fn render_lane(frame: &mut Frame, lane: &Lane, area: Rect) {
+    let reading = *lane.meter.borrow();
+    let status = lane.status.borrow().clone();
+
+    let connection = match (status.connected, status.queued) {
+        (true, 0) => Span::styled("connected", Style::default().fg(Color::Green)),
+        (true, n) => Span::styled(
+            format!("connected, replaying {}", n),
+            Style::default().fg(Color::Yellow),
+        ),
+        (false, 0) => Span::styled("offline", Style::default().fg(Color::Red)),
+        (false, n) => Span::styled(
+            format!("offline, {} queued", n),
+            Style::default().fg(Color::Red),
+        ),
+    };
+    let latency = match (
+        status.last_e2e_ms,
+        status.last_rtt_ms,
+        (status.finals > 0).then(|| status.total_e2e_ms / status.finals as f64),
+    ) {
+        (Some(e2e), Some(rtt), Some(avg)) => format!(
+            "e2e {:.0}ms rtt {:.0}ms avg {:.0}ms ({} finals)",
+            e2e, rtt, avg, status.finals
+        ),
+        _ => "no finals yet".to_string(),
+    };
+    let title = Line::from(vec![
+        Span::raw(format!(" {} ", lane.label.as_deref().unwrap_or("Input"))),
+        connection,
+        Span::raw(format!(" | {} ", latency)),
+    ]);
+    let block = Block::bordered().title(title);
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+    let [level, partial] = Layout::vertical([Constraint::Length(1); 2]).areas(inner);
+
+    let dbfs = meter::to_dbfs(reading.rms);
+    let state = if reading.speaking {
+        "SPEECH"
+    } else if reading.vad {
+        "vad"
+    } else {
+        ""
+    };
+    let color = if reading.speaking {
+        Color::Green
+    } else {
+        Color::Blue
+    };
+    frame.render_widget(
+        Gauge::default()
+            .ratio((((dbfs + 60.0) / 60.0).clamp(0.0, 1.0)) as f64)
+            .label(format!("{:.0}dB {}", dbfs, state))
+            .gauge_style(Style::default().fg(color)),
+        level,
+    );
+    frame.render_widget(
+        Paragraph::new(status.partial.as_str())
+            .style(Style::default().add_modifier(Modifier::ITALIC)),
+        partial,
+    );
+}
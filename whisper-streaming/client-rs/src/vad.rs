@@ -0,0 +1,82 @@
+use std::collections::VecDeque;
+
+/// WebRTC VAD aggressiveness, from most permissive to most strict.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VadMode {
+    Quality,
+    LowBitrate,
+    Aggressive,
+    VeryAggressive,
+}
+
+impl VadMode {
+    pub const ALL: [VadMode; 4] = [
+        VadMode::Quality,
+        VadMode::LowBitrate,
+        VadMode::Aggressive,
+        VadMode::VeryAggressive,
+    ];
+
+    pub fn create_vad(self) -> webrtc_vad::Vad {
+        let mode = match self {
+            VadMode::Quality => webrtc_vad::VadMode::Quality,
+            VadMode::LowBitrate => webrtc_vad::VadMode::LowBitrate,
+            VadMode::Aggressive => webrtc_vad::VadMode::Aggressive,
+            VadMode::VeryAggressive => webrtc_vad::VadMode::VeryAggressive,
+        };
+        webrtc_vad::Vad::new_with_rate_and_mode(webrtc_vad::SampleRate::Rate16kHz, mode)
+    }
+}
+
+/// How the VAD and energy detectors combine into a speech decision.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpeechLogic {
+    /// Both must agree
+    And,
+    /// Either is enough
+    Or,
+    /// Ignore the energy threshold
+    VadOnly,
+    /// Ignore the VAD
+    EnergyOnly,
+}
+
+impl SpeechLogic {
+    pub fn combine(self, vad: bool, loud: bool) -> bool {
+        match self {
+            SpeechLogic::And => vad && loud,
+            SpeechLogic::Or => vad || loud,
+            SpeechLogic::VadOnly => vad,
+            SpeechLogic::EnergyOnly => loud,
+        }
+    }
+}
+
+/// Moving-window vote over per-frame VAD decisions, so isolated false
+/// positives and dropouts don't toggle the speech state.
+pub struct VadSmoother {
+    window: VecDeque<bool>,
+    frames: usize,
+    ratio: f32,
+}
+
+impl VadSmoother {
+    /// Speech when at least `ratio` of the last `frames` decisions were
+    /// speech; 0.5 is a majority vote.
+    pub fn new(frames: usize, ratio: f32) -> Self {
+        Self {
+            window: VecDeque::with_capacity(frames),
+            frames: frames.max(1),
+            ratio,
+        }
+    }
+
+    pub fn update(&mut self, speech: bool) -> bool {
+        if self.window.len() == self.frames {
+            self.window.pop_front();
+        }
+        self.window.push_back(speech);
+        let votes = self.window.iter().filter(|&&s| s).count();
+        votes as f32 >= self.ratio * self.window.len() as f32
+    }
+}
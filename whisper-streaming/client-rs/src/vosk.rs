@@ -0,0 +1,85 @@
+//! Adapter for the Vosk WebSocket server protocol: a JSON config, then raw
+//! 16-bit PCM frames each answered with a `partial` or `text` result. An
+//! utterance ends with a reset, which returns its final text and readies
+//! the recognizer for the next one.
+
+use anyhow::Result;
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::connection::{self, ServerResponse, WsRead, WsStream, WsWrite};
+use crate::dsp;
+
+/// 250ms of 16kHz audio per frame, unless --frames-per-message
+const FRAME_SAMPLES: usize = 4000;
+/// The server matches control messages by their exact text
+const RESET: &str = r#"{"reset" : 1}"#;
+
+#[derive(Deserialize)]
+struct VoskResult {
+    text: Option<String>,
+}
+
+/// Tell the server the sample rate of the audio that follows.
+pub async fn configure(stream: &mut WsStream, sample_rate: u32) -> Result<()> {
+    let config = json!({ "config": { "sample_rate": sample_rate } });
+    stream.send(Message::Text(config.to_string())).await?;
+    Ok(())
+}
+
+/// Transcribe one utterance. An error means the connection is gone.
+pub async fn transcribe(
+    write: &mut WsWrite,
+    read: &mut WsRead,
+    audio: &[f32],
+    sample_rate: u32,
+    message_ms: Option<u32>,
+    send_timeout: Option<Duration>,
+) -> Result<Option<ServerResponse>> {
+    let pcm = dsp::f32_to_i16(audio, false);
+    let frames: Vec<Vec<u8>> = pcm
+        .chunks(connection::message_samples(
+            message_ms,
+            sample_rate,
+            FRAME_SAMPLES,
+        ))
+        .map(|frame| frame.iter().flat_map(|s| s.to_le_bytes()).collect())
+        .collect();
+    let replies = frames.len() + 1;
+    for frame in frames {
+        connection::send_within(write, Message::Binary(frame), send_timeout).await?;
+    }
+    connection::send_within(write, Message::Text(RESET.to_string()), send_timeout).await?;
+
+    // One reply per message; endpoints the server found mid-utterance come
+    // back as `text` results along the way
+    let mut texts = Vec::new();
+    let mut received = 0;
+    while received < replies {
+        let text = match read.next().await {
+            Some(Ok(Message::Text(text))) => text,
+            Some(Ok(Message::Close(_))) | None => anyhow::bail!("connection closed"),
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => return Err(e.into()),
+        };
+        received += 1;
+        if let Ok(VoskResult { text: Some(text) }) = serde_json::from_str(&text) {
+            if !text.trim().is_empty() {
+                texts.push(text.trim().to_string());
+            }
+        }
+    }
+    Ok(Some(ServerResponse {
+        msg_type: "result".to_string(),
+        text: Some(texts.join(" ")),
+        sample: None,
+        message: None,
+        utterance_id: None,
+        words: Vec::new(),
+        confidence: None,
+        speaker: None,
+    }))
+}
@@ -0,0 +1,129 @@
+//! Webhook delivery (--webhook): each final transcript is POSTed as the
+//! JSON `--format jsonl` prints for it, session ID included. Deliveries go
+//! out one at a time in order, each retried with backoff, so a slow or
+//! failing endpoint never holds up transcription.
+
+use anyhow::{bail, Context, Result};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use url::Url;
+
+use crate::backoff::Backoff;
+use crate::{batch, theme, Args};
+
+/// Finals held while the endpoint is slow or down
+const QUEUE: usize = 256;
+/// Longest wait for one attempt
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where and how to POST
+struct Target {
+    url: Url,
+    proxy: Option<String>,
+    tls: Option<native_tls::TlsConnector>,
+    retries: u32,
+}
+
+enum Job {
+    Deliver(String),
+    /// Answered once everything queued before it is done
+    Flush(oneshot::Sender<()>),
+}
+
+/// Queue of deliveries; clones share the one queue.
+#[derive(Clone)]
+pub struct Webhook {
+    tx: mpsc::Sender<Job>,
+}
+
+impl Webhook {
+    pub fn for_args(args: &Args) -> Result<Option<Self>> {
+        let Some(url) = &args.webhook else {
+            return Ok(None);
+        };
+        let url = Url::parse(url).with_context(|| format!("Invalid --webhook URL {}", url))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            bail!("--webhook must be http or https, not {}", url.scheme());
+        }
+        let target = Target {
+            url,
+            proxy: args.proxy.clone(),
+            tls: args.tls.clone(),
+            retries: args.webhook_retries,
+        };
+        let (tx, mut rx) = mpsc::channel(QUEUE);
+        tokio::spawn(async move {
+            while let Some(job) = rx.recv().await {
+                match job {
+                    Job::Deliver(body) => target.deliver(&body).await,
+                    Job::Flush(done) => {
+                        let _ = done.send(());
+                    }
+                }
+            }
+        });
+        Ok(Some(Self { tx }))
+    }
+
+    /// Queue one final's JSON, dropping it if the queue is full.
+    pub fn send(&self, body: String) {
+        if self.tx.try_send(Job::Deliver(body)).is_err() {
+            say!(
+                "{}",
+                theme::paint(
+                    theme::Role::Warning,
+                    "[webhook] Queue full, dropping a final"
+                )
+            );
+        }
+    }
+
+    /// Wait up to `wait` for queued finals to go out, before exiting.
+    pub async fn flush(&self, wait: Duration) {
+        let (done_tx, done_rx) = oneshot::channel();
+        if self.tx.send(Job::Flush(done_tx)).await.is_ok() {
+            let _ = tokio::time::timeout(wait, done_rx).await;
+        }
+    }
+}
+
+impl Target {
+    /// POST one final, retrying up to --webhook-retries times.
+    async fn deliver(&self, body: &str) {
+        let mut backoff = Backoff::new(500, 8000);
+        let mut attempt = 0;
+        loop {
+            let post = batch::post(
+                &self.url,
+                self.proxy.as_deref(),
+                self.tls.as_ref(),
+                "Content-Type: application/json\r\n",
+                body.as_bytes(),
+            );
+            let result = tokio::time::timeout(ATTEMPT_TIMEOUT, post)
+                .await
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("Timed out")))
+                .and_then(|response| batch::checked_body(&response));
+            match result {
+                Ok(_) => return,
+                Err(e) if attempt < self.retries => {
+                    attempt += 1;
+                    let delay = backoff.next_delay();
+                    verbose!(
+                        "[webhook] {}; retry {}/{} in {:.1}s",
+                        e,
+                        attempt,
+                        self.retries,
+                        delay.as_secs_f32()
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => {
+                    let warning = format!("[webhook] Giving up on a final: {}", e);
+                    say!("{}", theme::paint(theme::Role::Warning, &warning));
+                    return;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Experimental WebTransport (HTTP/3 over QUIC) transport. Each utterance
+//! goes on its own bidirectional stream, so a lost packet only stalls the
+//! utterance it belongs to rather than everything queued behind it as on a
+//! WebSocket.
+
+use anyhow::{Context, Result};
+use futures_util::FutureExt;
+use tokio::io::AsyncReadExt;
+use wtransport::endpoint::ConnectOptions;
+use wtransport::{ClientConfig, Connection, Endpoint};
+
+use crate::connection::{self, ServerResponse};
+use crate::Args;
+
+/// Largest reply read from a stream
+const MAX_REPLY: u64 = 1 << 20;
+
+pub struct WtStream {
+    connection: Connection,
+}
+
+impl WtStream {
+    /// Open a session at `url`, an `https://` URL.
+    pub async fn open(url: &str, args: &Args) -> Result<Self> {
+        let builder = ClientConfig::builder().with_bind_default();
+        let builder = if args.insecure {
+            builder.with_no_cert_validation()
+        } else {
+            builder.with_native_certs()
+        };
+        let config = match connection::ping_settings(args) {
+            Some((interval, timeout)) => builder
+                .keep_alive_interval(Some(interval))
+                .max_idle_timeout(Some(interval + timeout))?
+                .build(),
+            None => builder.build(),
+        };
+        // Same URL and credentials as the WebSocket handshake
+        let uri = connection::handshake_request(url, args)?.uri().to_string();
+        let options = connection::handshake_headers(args)
+            .into_iter()
+            .fold(ConnectOptions::builder(uri), |options, (name, value)| {
+                options.add_header(name, value)
+            })
+            .build();
+        let connection = Endpoint::client(config)?
+            .connect(options)
+            .await
+            .with_context(|| format!("Cannot open a WebTransport session at {}", url))?;
+        Ok(Self { connection })
+    }
+
+    /// An error once the session is closed; QUIC keepalive does the probing.
+    pub fn check(&self) -> Result<()> {
+        match self.connection.closed().now_or_never() {
+            Some(e) => Err(e.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Send a message that has no reply on a stream of its own.
+    pub async fn send(&self, message: &[u8]) -> Result<()> {
+        let mut stream = self.connection.open_uni().await?.await?;
+        stream.write_all(message).await?;
+        stream.finish().await?;
+        Ok(())
+    }
+
+    /// Send one utterance's message on a new stream and read the reply the
+    /// server writes back before finishing it.
+    pub async fn request(&self, message: &[u8]) -> Result<Option<ServerResponse>> {
+        let (mut send, recv) = self.connection.open_bi().await?.await?;
+        send.write_all(message).await?;
+        send.finish().await?;
+        let mut reply = Vec::new();
+        recv.take(MAX_REPLY).read_to_end(&mut reply).await?;
+        Ok(serde_json::from_slice(&reply).ok())
+    }
+}
@@ -0,0 +1,60 @@
+//! Word-level diff between successive partials, for highlighting the words
+//! that changed (see `paint_partial`).
+
+use crate::endpoint;
+
+/// For each word of `text`, whether it carries over from `previous`.
+/// Words are compared like the endpointer does, ignoring case and
+/// punctuation, and matched along their longest common subsequence.
+pub fn kept(previous: &str, text: &str) -> Vec<bool> {
+    let old: Vec<String> = previous
+        .split_whitespace()
+        .map(endpoint::normalize)
+        .collect();
+    let new: Vec<String> = text.split_whitespace().map(endpoint::normalize).collect();
+    // lcs[i][j] is the subsequence length for old[i..], new[j..]
+    let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut kept = vec![false; new.len()];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            kept[j] = true;
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insertion_marks_only_the_new_word() {
+        assert_eq!(kept("a b", "a x b"), [true, false, true]);
+    }
+
+    #[test]
+    fn everything_is_new_after_an_empty_partial() {
+        assert_eq!(kept("", "a b"), [false, false]);
+    }
+
+    #[test]
+    fn case_and_punctuation_are_ignored() {
+        assert_eq!(kept("hello world", "Hello, world."), [true, true]);
+    }
+}
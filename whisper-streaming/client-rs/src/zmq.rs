@@ -0,0 +1,42 @@
+//! ZeroMQ transport for pipelines that already pass audio and text over ZMQ:
+//! a DEALER socket sends each utterance as one message to a ROUTER at the
+//! server URL (`tcp://host:port` or `ipc:///path`) and gets the JSON reply
+//! back as the next message.
+
+use anyhow::{Context, Result};
+use zeromq::{DealerSocket, Socket, SocketRecv, SocketSend, ZmqMessage};
+
+use crate::connection::ServerResponse;
+
+pub struct ZmqStream {
+    socket: DealerSocket,
+}
+
+impl ZmqStream {
+    pub async fn open(endpoint: &str) -> Result<Self> {
+        let mut socket = DealerSocket::new();
+        socket
+            .connect(endpoint)
+            .await
+            .with_context(|| format!("Cannot connect to {}", endpoint))?;
+        Ok(Self { socket })
+    }
+
+    /// Send a message that has no reply.
+    pub async fn send(&mut self, message: Vec<u8>) -> Result<()> {
+        self.socket.send(ZmqMessage::from(message)).await?;
+        Ok(())
+    }
+
+    /// Send one utterance's message and wait for the reply. Its last frame
+    /// is the payload, so routers that add an empty delimiter frame, as for
+    /// REQ peers, work too.
+    pub async fn request(&mut self, message: Vec<u8>) -> Result<Option<ServerResponse>> {
+        self.send(message).await?;
+        let reply = self.socket.recv().await?;
+        Ok(reply
+            .into_vec()
+            .last()
+            .and_then(|payload| serde_json::from_slice(payload).ok()))
+    }
+}
@@ -1,6 +1,6 @@
 # server/backends/base.py
 from abc import ABC, abstractmethod
-from dataclasses import dataclass
+from dataclasses import dataclass, field
 import numpy as np
 
 
@@ -9,6 +9,16 @@ class Segment:
     start: float
     end: float
     text: str
+    # 0 to 1, from the decoder's average log probability
+    confidence: float | None = None
+
+
+@dataclass
+class Word:
+    start: float
+    end: float
+    word: str
+    probability: float | None = None
 
 
 @dataclass
@@ -17,6 +27,8 @@ class TranscriptResult:
     segments: list[Segment]
     language: str
     processing_time_ms: float
+    # Only when asked for with word_timestamps
+    words: list[Word] = field(default_factory=list)
 
 
 class WhisperBackend(ABC):
@@ -30,7 +42,10 @@ class WhisperBackend(ABC):
         self,
         audio: np.ndarray,
         sample_rate: int,
-        initial_prompt: str | None = None
+        initial_prompt: str | None = None,
+        language: str | None = None,
+        word_timestamps: bool = False
     ) -> TranscriptResult:
-        """Transcribe audio and return normalized result."""
+        """Transcribe audio and return normalized result, with word timings
+        if asked for and the backend has them."""
         pass
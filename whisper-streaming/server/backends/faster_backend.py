@@ -1,8 +1,9 @@
 # server/backends/faster_backend.py
+import math
 import time
 import numpy as np
 from faster_whisper import WhisperModel
-from .base import WhisperBackend, TranscriptResult, Segment
+from .base import WhisperBackend, TranscriptResult, Segment, Word
 
 
 class FasterBackend(WhisperBackend):
@@ -16,7 +17,9 @@ class FasterBackend(WhisperBackend):
         self,
         audio: np.ndarray,
         sample_rate: int,
-        initial_prompt: str | None = None
+        initial_prompt: str | None = None,
+        language: str | None = None,
+        word_timestamps: bool = False
     ) -> TranscriptResult:
         if self.model is None:
             raise RuntimeError("Model not loaded. Call load_model() first.")
@@ -25,13 +28,22 @@ class FasterBackend(WhisperBackend):
 
         segments_gen, info = self.model.transcribe(
             audio,
-            initial_prompt=initial_prompt
+            initial_prompt=initial_prompt,
+            language=language,
+            word_timestamps=word_timestamps
         )
 
-        segments = [
-            Segment(start=s.start, end=s.end, text=s.text.strip())
-            for s in segments_gen
-        ]
+        segments = []
+        words = []
+        for s in segments_gen:
+            segments.append(Segment(
+                start=s.start, end=s.end, text=s.text.strip(), confidence=math.exp(s.avg_logprob)
+            ))
+            if word_timestamps:
+                words.extend(
+                    Word(start=w.start, end=w.end, word=w.word.strip(), probability=w.probability)
+                    for w in s.words
+                )
 
         processing_time_ms = (time.perf_counter() - start_time) * 1000
 
@@ -41,5 +53,6 @@ class FasterBackend(WhisperBackend):
             text=full_text,
             segments=segments,
             language=info.language,
-            processing_time_ms=processing_time_ms
+            processing_time_ms=processing_time_ms,
+            words=words
         )
@@ -108,7 +108,9 @@ class HailoBackend(WhisperBackend):
         self,
         audio: np.ndarray,
         sample_rate: int,
-        initial_prompt: str | None = None
+        initial_prompt: str | None = None,
+        language: str | None = None,
+        word_timestamps: bool = False
     ) -> TranscriptResult:
         """Transcribe audio using Hailo-10H.
 
@@ -116,6 +118,8 @@ class HailoBackend(WhisperBackend):
             audio: Audio samples as float32 numpy array
             sample_rate: Sample rate (will be resampled to 16kHz if needed)
             initial_prompt: Optional prompt for context (not supported by Hailo)
+            language: Language hint (Hailo Whisper is English only)
+            word_timestamps: Ignored, the pipeline has no word timings
 
         Returns:
             TranscriptResult with transcription text and timing info
@@ -1,8 +1,9 @@
 # server/backends/mlx_backend.py
+import math
 import time
 import numpy as np
 import mlx_whisper
-from .base import WhisperBackend, TranscriptResult, Segment
+from .base import WhisperBackend, TranscriptResult, Segment, Word
 
 
 class MLXBackend(WhisperBackend):
@@ -16,7 +17,9 @@ class MLXBackend(WhisperBackend):
         self,
         audio: np.ndarray,
         sample_rate: int,
-        initial_prompt: str | None = None
+        initial_prompt: str | None = None,
+        language: str | None = None,
+        word_timestamps: bool = False
     ) -> TranscriptResult:
         if self.model_path is None:
             raise RuntimeError("Model not loaded. Call load_model() first.")
@@ -26,19 +29,32 @@ class MLXBackend(WhisperBackend):
         result = mlx_whisper.transcribe(
             audio,
             path_or_hf_repo=self.model_path,
-            initial_prompt=initial_prompt
+            initial_prompt=initial_prompt,
+            language=language,
+            word_timestamps=word_timestamps
         )
 
         processing_time_ms = (time.perf_counter() - start_time) * 1000
 
         segments = [
-            Segment(start=s["start"], end=s["end"], text=s["text"].strip())
+            Segment(
+                start=s["start"],
+                end=s["end"],
+                text=s["text"].strip(),
+                confidence=math.exp(s["avg_logprob"]) if "avg_logprob" in s else None
+            )
             for s in result.get("segments", [])
         ]
+        words = [
+            Word(start=w["start"], end=w["end"], word=w["word"].strip(), probability=w.get("probability"))
+            for s in result.get("segments", [])
+            for w in s.get("words", [])
+        ]
 
         return TranscriptResult(
             text=result.get("text", "").strip(),
             segments=segments,
             language=result.get("language", "unknown"),
-            processing_time_ms=processing_time_ms
+            processing_time_ms=processing_time_ms,
+            words=words
         )
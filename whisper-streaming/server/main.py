@@ -2,12 +2,15 @@
 import os
 import re
 import json
+import uuid
 import base64
+import struct
 import signal
 import asyncio
 import logging
 import numpy as np
-from websockets.asyncio.server import serve
+from collections import OrderedDict
+from websockets.asyncio.server import serve, unix_serve
 from websockets.exceptions import ConnectionClosed
 
 from opentelemetry import trace
@@ -61,6 +64,237 @@ logging.basicConfig(
 logger = logging.getLogger(__name__)
 
 
+# Binary audio frames, offered by clients as a WebSocket subprotocol:
+# b"WPCM", sample format (0 = float32, 1 = int16, 2 = Opus), flags (bit 0 for
+# a partial), 2 reserved bytes, sample rate (uint32), 16-byte utterance ID,
+# then samples; all little-endian.
+# Opus payloads are 20ms packets, each prefixed with its length (uint16).
+BINARY_SUBPROTOCOL = "whisper-pcm.v1"
+OPUS_SUBPROTOCOL = "whisper-opus.v1"
+# MessagePack messages both ways: the JSON fields, with audio as raw bytes
+MSGPACK_SUBPROTOCOL = "whisper-msgpack.v1"
+# Protobuf messages of proto/whisper.proto both ways
+PROTOBUF_SUBPROTOCOL = "whisper-protobuf.v1"
+BINARY_HEADER = struct.Struct("<4sBB2xI16s")
+
+# Sessions kept after their connection closes, for clients that reconnect
+# with a resume message
+MAX_RESUMABLE_SESSIONS = 256
+
+try:
+    import opuslib
+except ImportError:
+    opuslib = None
+
+try:
+    import msgpack
+except ImportError:
+    msgpack = None
+
+
+def server_capabilities() -> list[str]:
+    """Features advertised in config_ack."""
+    capabilities = ["resume", "language", "hotwords", "binary", "subscribe", "ack", "word_timestamps", "protobuf"]
+    if msgpack:
+        capabilities.append("msgpack")
+    if opuslib:
+        capabilities.append("opus")
+    return capabilities
+
+
+def select_subprotocol(connection, subprotocols):
+    """Pick the best binary encoding offered; plain JSON clients offer none."""
+    if opuslib and OPUS_SUBPROTOCOL in subprotocols:
+        return OPUS_SUBPROTOCOL
+    if msgpack and MSGPACK_SUBPROTOCOL in subprotocols:
+        return MSGPACK_SUBPROTOCOL
+    if PROTOBUF_SUBPROTOCOL in subprotocols:
+        return PROTOBUF_SUBPROTOCOL
+    if BINARY_SUBPROTOCOL in subprotocols:
+        return BINARY_SUBPROTOCOL
+    return None
+
+
+def parse_msgpack_message(data: bytes) -> dict:
+    """Decode a MessagePack message, unpacking raw float32 audio."""
+    message = msgpack.unpackb(data, raw=False)
+    if not isinstance(message, dict):
+        raise ValueError("expected a map")
+    if isinstance(message.get("audio"), bytes):
+        message["samples"] = np.frombuffer(message.pop("audio"), dtype="<f4").astype(np.float32)
+    return message
+
+
+def _varint(data: bytes, pos: int) -> tuple[int, int]:
+    """Decode a protobuf varint at `pos`, returning it and the next position."""
+    value = shift = 0
+    while True:
+        if pos >= len(data):
+            raise ValueError("truncated varint")
+        byte = data[pos]
+        pos += 1
+        value |= (byte & 0x7F) << shift
+        if not byte & 0x80:
+            return value, pos
+        shift += 7
+
+
+def _protobuf_fields(data: bytes) -> dict:
+    """Field number to value of a protobuf message: ints for varints, bytes
+    for length-delimited fields. Fixed-width fields are skipped; none of the
+    client's messages have any."""
+    fields = {}
+    pos = 0
+    while pos < len(data):
+        key, pos = _varint(data, pos)
+        field, wire_type = key >> 3, key & 7
+        if wire_type == 0:
+            fields[field], pos = _varint(data, pos)
+        elif wire_type == 2:
+            length, pos = _varint(data, pos)
+            if pos + length > len(data):
+                raise ValueError("truncated field")
+            fields[field] = data[pos:pos + length]
+            pos += length
+        elif wire_type == 1:
+            pos += 8
+        elif wire_type == 5:
+            pos += 4
+        else:
+            raise ValueError(f"unsupported wire type {wire_type}")
+    return fields
+
+
+def parse_protobuf_message(data: bytes) -> dict:
+    """Decode a ClientMessage into the equivalent JSON message's fields.
+
+    An AudioFrame becomes an `audio_frame` message, to be collected until the
+    utterance's VadEnd.
+    """
+    top = _protobuf_fields(data)
+    if 1 in top:
+        frame = _protobuf_fields(top[1])
+        samples = frame.get(4, b"")
+        if frame.get(3, 0) == 1:
+            audio = np.frombuffer(samples, dtype="<i2").astype(np.float32) / 32768.0
+        else:
+            audio = np.frombuffer(samples, dtype="<f4").astype(np.float32)
+        return {
+            "type": "audio_frame",
+            "utterance_id": frame.get(1, b"").decode(),
+            "sample_rate": frame.get(2, 16000),
+            "samples": audio,
+            "partial": bool(frame.get(5, 0)),
+        }
+    if 2 in top:
+        end = _protobuf_fields(top[2])
+        return {"type": "vad_end", "utterance_id": end.get(1, b"").decode()}
+    if 3 in top:
+        resume = _protobuf_fields(top[3])
+        return {
+            "type": "resume",
+            "session_id": resume.get(1, b"").decode(),
+            "last_utterance_id": resume.get(2, b"").decode(),
+        }
+    raise ValueError("empty ClientMessage")
+
+
+def _protobuf_field(field: int, value) -> bytes:
+    """One encoded field: str and bytes length-delimited, float as fixed32,
+    bool and int as varints."""
+    def varint(n: int) -> bytes:
+        out = bytearray()
+        while True:
+            byte = n & 0x7F
+            n >>= 7
+            if n:
+                out.append(byte | 0x80)
+            else:
+                out.append(byte)
+                return bytes(out)
+
+    if isinstance(value, str):
+        value = value.encode()
+    if isinstance(value, bytes):
+        return varint(field << 3 | 2) + varint(len(value)) + value
+    if isinstance(value, float):
+        return varint(field << 3 | 5) + struct.pack("<f", value)
+    return varint(field << 3) + varint(int(value))
+
+
+def encode_protobuf_result(result: dict) -> bytes:
+    """A transcribe reply as a ServerMessage: FinalResult, Noise or Error."""
+    utterance_id = result.get("utterance_id") or ""
+    if result["type"] == "noise":
+        body = _protobuf_field(1, utterance_id) + _protobuf_field(2, result.get("sample", ""))
+        return _protobuf_field(3, body)
+    if result["type"] == "error":
+        body = _protobuf_field(1, utterance_id) + _protobuf_field(2, result.get("message", ""))
+        return _protobuf_field(4, body)
+    body = (
+        _protobuf_field(1, utterance_id)
+        + _protobuf_field(2, result.get("text", ""))
+        + _protobuf_field(3, result.get("language") or "")
+        + _protobuf_field(4, float(result.get("processing_time_ms", 0.0)))
+    )
+    for word in result.get("words", []):
+        encoded = (
+            _protobuf_field(1, word["word"])
+            + _protobuf_field(2, float(word["start"]))
+            + _protobuf_field(3, float(word["end"]))
+        )
+        if word.get("probability") is not None:
+            encoded += _protobuf_field(4, float(word["probability"]))
+        body += _protobuf_field(5, encoded)
+    if result.get("confidence") is not None:
+        body += _protobuf_field(6, float(result["confidence"]))
+    if result.get("speaker") is not None:
+        body += _protobuf_field(7, str(result["speaker"]))
+    return _protobuf_field(2, body)
+
+
+def decode_opus(payload: bytes, sample_rate: int) -> np.ndarray:
+    """Decode length-prefixed 20ms Opus packets to float32 samples."""
+    if opuslib is None:
+        raise ValueError("Opus frame but opuslib is not installed")
+    decoder = opuslib.Decoder(sample_rate, 1)
+    frame_size = sample_rate // 50
+    chunks = []
+    offset = 0
+    while offset + 2 <= len(payload):
+        (length,) = struct.unpack_from("<H", payload, offset)
+        packet = payload[offset + 2:offset + 2 + length]
+        offset += 2 + length
+        pcm = decoder.decode_float(packet, frame_size)
+        chunks.append(np.frombuffer(pcm, dtype=np.float32))
+    return np.concatenate(chunks) if chunks else np.zeros(0, dtype=np.float32)
+
+
+def parse_binary_frame(data: bytes) -> dict:
+    """Decode a binary audio frame into a transcribe message."""
+    if len(data) < BINARY_HEADER.size:
+        raise ValueError("frame shorter than header")
+    magic, sample_format, flags, sample_rate, utterance_id = BINARY_HEADER.unpack_from(data)
+    if magic != b"WPCM":
+        raise ValueError(f"bad magic {magic!r}")
+    payload = data[BINARY_HEADER.size:]
+    if sample_format == 0:
+        audio = np.frombuffer(payload, dtype="<f4").astype(np.float32)
+    elif sample_format == 1:
+        audio = np.frombuffer(payload, dtype="<i2").astype(np.float32) / 32768.0
+    elif sample_format == 2:
+        audio = decode_opus(payload, sample_rate)
+    else:
+        raise ValueError(f"unknown sample format {sample_format}")
+    return {
+        "type": "transcribe",
+        "samples": audio,
+        "sample_rate": sample_rate,
+        "utterance_id": str(uuid.UUID(bytes=utterance_id)),
+        "partial": bool(flags & 1),
+    }
+
+
 def clean_hallucination(text: str) -> str | None:
     """Clean text by removing hallucinations. Returns None if entirely noise."""
     if not text or len(text.strip()) < 2:
@@ -121,13 +355,49 @@ class TranscriptionSession:
         self.backend = backend
         self.sample_rate = sample_rate
         self.previous_transcript = ""
+        # Utterance of the last result sent
+        self.last_utterance_id = None
+        # Reply to `last_utterance_id`, for a client that sends it again,
+        # and how many samples it was for: partials resend a growing
+        # utterance under the same ID
+        self.last_result = None
+        self.last_samples = 0
+        # From the client's config message
+        self.language = None
+        self.hotwords: list[str] = []
+        # Connection that subscribed to this session's results, if not the
+        # one its audio arrives on
+        self.results = None
+        # Acknowledgments asked for on resume: audio received so far, in
+        # bytes of 32-bit float samples
+        self.acks = False
+        self.received_bytes = 0
+        self.word_timestamps = False
+
+    def configure(self, message: dict) -> None:
+        """Apply a client's config message."""
+        self.language = message.get("language") or None
+        self.sample_rate = message.get("sample_rate", self.sample_rate)
+        self.hotwords = [str(w) for w in message.get("hotwords", []) if str(w).strip()]
+        self.word_timestamps = bool(message.get("word_timestamps", False))
+
+    def prompt(self) -> str | None:
+        """Hotwords, then the previous transcript, as the decoder prompt."""
+        parts = []
+        if self.hotwords:
+            parts.append(", ".join(self.hotwords) + ".")
+        if self.previous_transcript:
+            parts.append(self.previous_transcript)
+        return " ".join(parts) or None
 
     def transcribe(self, audio: np.ndarray) -> dict:
         """Transcribe audio batch using prompt conditioning."""
         result = self.backend.transcribe(
             audio,
             self.sample_rate,
-            initial_prompt=self.previous_transcript or None
+            initial_prompt=self.prompt(),
+            language=self.language,
+            word_timestamps=self.word_timestamps
         )
 
         raw_text = result.text.strip()
@@ -143,13 +413,25 @@ class TranscriptionSession:
         # Only update prompt with valid transcriptions
         self.previous_transcript = text
 
-        return {
+        reply = {
             "type": "result",
             "text": text,
-            "segments": [{"start": s.start, "end": s.end, "text": s.text} for s in result.segments],
+            "segments": [
+                {"start": s.start, "end": s.end, "text": s.text, "confidence": s.confidence}
+                for s in result.segments
+            ],
             "language": result.language,
             "processing_time_ms": result.processing_time_ms
         }
+        confidences = [s.confidence for s in result.segments if s.confidence is not None]
+        if confidences:
+            reply["confidence"] = sum(confidences) / len(confidences)
+        if self.word_timestamps:
+            reply["words"] = [
+                {"word": w.word, "start": w.start, "end": w.end, "probability": w.probability}
+                for w in result.words
+            ]
+        return reply
 
 
 def create_app():
@@ -166,20 +448,107 @@ def create_app():
     backend.transcribe(warmup_audio, 16000)
     logger.info("Model ready")
 
+    sessions: OrderedDict[str, TranscriptionSession] = OrderedDict()
+
+    def resume_session(session: TranscriptionSession, message: dict) -> TranscriptionSession:
+        """Continue the session named in a resume message, or register this one under it."""
+        resume_id = message.get("session_id")
+        if not resume_id:
+            return session
+        previous = sessions.get(resume_id)
+        if previous is None:
+            sessions[resume_id] = session
+            while len(sessions) > MAX_RESUMABLE_SESSIONS:
+                sessions.popitem(last=False)
+            return session
+        sessions.move_to_end(resume_id)
+        last_utterance_id = message.get("last_utterance_id") or None
+        if last_utterance_id != previous.last_utterance_id:
+            logger.info(
+                f"Resumed session {resume_id}; client missed the result for "
+                f"{previous.last_utterance_id}"
+            )
+        else:
+            logger.info(f"Resumed session {resume_id}")
+        return previous
+
+    def subscribe_session(websocket, message: dict) -> TranscriptionSession | None:
+        """Send the results of the session in a subscribe message to `websocket`.
+
+        The subscription may arrive before the audio connection's resume, so
+        an unknown session is registered for that resume to pick up.
+        """
+        session_id = message.get("session_id")
+        if not session_id:
+            return None
+        session = sessions.get(session_id)
+        if session is None:
+            session = TranscriptionSession(backend=backend)
+            sessions[session_id] = session
+            while len(sessions) > MAX_RESUMABLE_SESSIONS:
+                sessions.popitem(last=False)
+        session.results = websocket
+        logger.info(f"Results of session {session_id} go to {websocket.remote_address}")
+        return session
+
     async def handler(websocket):
         client_addr = websocket.remote_address
         logger.info(f"Client connected: {client_addr}")
 
         session = TranscriptionSession(backend=backend)
+        use_msgpack = websocket.subprotocol == MSGPACK_SUBPROTOCOL
+        use_protobuf = websocket.subprotocol == PROTOBUF_SUBPROTOCOL
+        # Protobuf audio frames of the utterance in progress, until its VadEnd
+        frames: list[np.ndarray] = []
+        frame_info: dict = {}
         loop = asyncio.get_event_loop()
 
         try:
             async for raw_message in websocket:
-                try:
-                    message = json.loads(raw_message)
-                except json.JSONDecodeError as e:
-                    logger.error(f"Invalid JSON: {e}")
+                if isinstance(raw_message, bytes):
+                    try:
+                        if use_msgpack:
+                            message = parse_msgpack_message(raw_message)
+                        elif use_protobuf:
+                            message = parse_protobuf_message(raw_message)
+                        else:
+                            message = parse_binary_frame(raw_message)
+                    except ValueError as e:
+                        logger.error(f"Invalid binary frame: {e}")
+                        await websocket.send(json.dumps({
+                            "type": "error",
+                            "message": f"invalid binary frame: {e}",
+                        }))
+                        continue
+                else:
+                    try:
+                        message = json.loads(raw_message)
+                    except json.JSONDecodeError as e:
+                        logger.error(f"Invalid JSON: {e}")
+                        await websocket.send(json.dumps({
+                            "type": "error",
+                            "message": f"invalid JSON: {e}",
+                        }))
+                        continue
+
+                if use_protobuf and message.get("type") == "audio_frame":
+                    if message["utterance_id"] != frame_info.get("utterance_id"):
+                        frames = []
+                    frames.append(message.pop("samples"))
+                    frame_info = message
                     continue
+                if use_protobuf and message.get("type") == "vad_end":
+                    # Transcribe what arrived for the utterance as one
+                    # message; a client that repeats it sends the frames again
+                    if message["utterance_id"] != frame_info.get("utterance_id"):
+                        frames, frame_info = [], {}
+                    message = {
+                        **frame_info,
+                        "type": "transcribe",
+                        "samples": np.concatenate(frames) if frames else np.zeros(0, dtype=np.float32),
+                        "utterance_id": message["utterance_id"],
+                    }
+                    frames, frame_info = [], {}
 
                 msg_type = message.get("type")
                 traceparent_str = message.get("traceparent")
@@ -202,7 +571,50 @@ def create_app():
                     except (IndexError, ValueError):
                         logger.warning(f"Invalid traceparent: {traceparent_str}")
 
-                if msg_type == "transcribe":
+                if msg_type == "resume":
+                    session = resume_session(session, message)
+                    if message.get("ack"):
+                        # The client retransmits from this offset
+                        session.acks = True
+                        try:
+                            await websocket.send(json.dumps({
+                                "type": "ack",
+                                "offset": session.received_bytes,
+                            }))
+                        except ConnectionClosed:
+                            break
+
+                elif msg_type == "subscribe":
+                    subscribed = subscribe_session(websocket, message)
+                    if subscribed is not None:
+                        session = subscribed
+
+                elif msg_type == "config":
+                    session.configure(message)
+                    client = message.get("client") or {}
+                    if client:
+                        logger.info(
+                            f"Client {client_addr}: {client.get('name')} {client.get('version')} "
+                            f"on {client.get('os')}/{client.get('arch')}, "
+                            f"device {client.get('device') or 'n/a'}, {client.get('encoding')}, "
+                            f"params {json.dumps(client.get('params', {}))}"
+                        )
+                    requested_model = message.get("model")
+                    if requested_model and requested_model != model_name:
+                        logger.info(f"Client asked for model '{requested_model}', serving '{model_name}'")
+                    ack = {
+                        "type": "config_ack",
+                        "model": model_name,
+                        "language": session.language or "auto",
+                        "capabilities": server_capabilities(),
+                    }
+                    # Always JSON: clients read text frames as JSON whatever the subprotocol
+                    try:
+                        await websocket.send(json.dumps(ack))
+                    except ConnectionClosed:
+                        break
+
+                elif msg_type == "transcribe":
                     with tracer.start_as_current_span(
                         "stt-transcribe",
                         context=parent_context,
@@ -211,10 +623,11 @@ def create_app():
                         if session_id:
                             span.set_attribute("session.id", session_id)
 
-                        audio_b64 = message.get("audio", "")
                         sample_rate = message.get("sample_rate", 16000)
-                        audio_bytes = base64.b64decode(audio_b64)
-                        audio = np.frombuffer(audio_bytes, dtype=np.float32)
+                        audio = message.get("samples")
+                        if audio is None:
+                            audio_bytes = base64.b64decode(message.get("audio", ""))
+                            audio = np.frombuffer(audio_bytes, dtype=np.float32)
 
                         duration_ms = len(audio) / sample_rate * 1000
                         span.set_attribute("audio.duration_ms", duration_ms)
@@ -222,8 +635,39 @@ def create_app():
 
                         logger.info(f"Transcribing {duration_ms:.0f}ms audio")
 
-                        session.sample_rate = sample_rate
-                        result = await loop.run_in_executor(None, session.transcribe, audio)
+                        utterance_id = message.get("utterance_id")
+                        # Audio so far of an utterance still in progress:
+                        # the final sends it again, so it isn't counted or
+                        # kept for a repeat
+                        partial = bool(message.get("partial"))
+                        if (
+                            not partial
+                            and utterance_id
+                            and utterance_id == session.last_utterance_id
+                            and session.last_result is not None
+                            and len(audio) == session.last_samples
+                        ):
+                            # The client didn't get the reply and sent it again
+                            logger.info(f"Repeated utterance {utterance_id}, resending its result")
+                            result = dict(session.last_result)
+                        else:
+                            if session.acks and not partial:
+                                session.received_bytes += len(audio) * 4
+                                try:
+                                    await websocket.send(json.dumps({
+                                        "type": "ack",
+                                        "offset": session.received_bytes,
+                                    }))
+                                except ConnectionClosed:
+                                    break
+                            session.sample_rate = sample_rate
+                            result = await loop.run_in_executor(None, session.transcribe, audio)
+                            if utterance_id:
+                                result["utterance_id"] = utterance_id
+                            if not partial:
+                                session.last_utterance_id = utterance_id
+                                session.last_result = dict(result)
+                                session.last_samples = len(audio)
 
                         if result["type"] == "noise":
                             span.set_attribute("result.type", "noise")
@@ -242,17 +686,35 @@ def create_app():
                         if ctx.is_valid:
                             result["traceparent"] = f"00-{format(ctx.trace_id, '032x')}-{format(ctx.span_id, '016x')}-01"
 
+                        if session.results is not None and session.results is not websocket:
+                            # Subscribers negotiate no subprotocol, so always JSON
+                            try:
+                                await session.results.send(json.dumps(result))
+                                continue
+                            except ConnectionClosed:
+                                session.results = None
                         try:
-                            await websocket.send(json.dumps(result))
+                            if use_msgpack:
+                                await websocket.send(msgpack.packb(result))
+                            elif use_protobuf:
+                                await websocket.send(encode_protobuf_result(result))
+                            else:
+                                await websocket.send(json.dumps(result))
                         except ConnectionClosed:
                             break
 
                 else:
                     logger.warning(f"Unknown message type: {msg_type}")
+                    await websocket.send(json.dumps({
+                        "type": "warning",
+                        "message": f"unknown message type: {msg_type}",
+                    }))
 
         except ConnectionClosed:
             pass
         finally:
+            if session.results is websocket:
+                session.results = None
             logger.info(f"Client disconnected: {client_addr}")
 
     return handler
@@ -261,10 +723,15 @@ def create_app():
 async def main():
     host = os.environ.get("HOST", "0.0.0.0")
     port = int(os.environ.get("PORT", "8765"))
+    # Listen on a Unix domain socket instead, for clients on this machine
+    socket_path = os.environ.get("UNIX_SOCKET")
 
     handler = create_app()
 
-    logger.info(f"Starting WebSocket server on ws://{host}:{port}")
+    if socket_path:
+        logger.info(f"Starting WebSocket server on unix://{socket_path}")
+    else:
+        logger.info(f"Starting WebSocket server on ws://{host}:{port}")
     logger.info("Batch transcription with prompt conditioning")
 
     stop = asyncio.Event()
@@ -273,7 +740,12 @@ async def main():
     loop.add_signal_handler(signal.SIGINT, stop.set)
     loop.add_signal_handler(signal.SIGTERM, stop.set)
 
-    async with serve(handler, host, port, max_size=10 * 1024 * 1024):
+    options = dict(max_size=10 * 1024 * 1024, select_subprotocol=select_subprotocol)
+    if socket_path:
+        server = unix_serve(handler, socket_path, **options)
+    else:
+        server = serve(handler, host, port, **options)
+    async with server:
         await stop.wait()
 
     logger.info("Server stopped")
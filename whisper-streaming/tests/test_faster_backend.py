@@ -44,6 +44,39 @@ def test_faster_backend_transcribe(mock_model_class):
     assert result.language == "en"
 
 
+@patch("server.backends.faster_backend.WhisperModel")
+def test_faster_backend_word_timestamps(mock_model_class):
+    mock_word = MagicMock()
+    mock_word.start = 0.1
+    mock_word.end = 0.5
+    mock_word.word = " hello"
+    mock_word.probability = 0.9
+
+    mock_segment = MagicMock()
+    mock_segment.start = 0.0
+    mock_segment.end = 1.0
+    mock_segment.text = "hello"
+    mock_segment.avg_logprob = 0.0
+    mock_segment.words = [mock_word]
+
+    mock_model = MagicMock()
+    mock_model.transcribe.return_value = ([mock_segment], MagicMock(language="en"))
+    mock_model_class.return_value = mock_model
+
+    backend = FasterBackend()
+    backend.load_model("small")
+
+    audio = np.zeros(16000, dtype=np.float32)
+    result = backend.transcribe(audio, 16000, word_timestamps=True)
+
+    assert mock_model.transcribe.call_args.kwargs["word_timestamps"] is True
+    assert len(result.words) == 1
+    assert result.words[0].word == "hello"
+    assert result.words[0].start == 0.1
+    assert result.words[0].probability == 0.9
+    assert result.segments[0].confidence == 1.0
+
+
 @patch("server.backends.faster_backend.WhisperModel")
 def test_faster_backend_transcribe_without_model_raises(mock_model_class):
     backend = FasterBackend()